@@ -0,0 +1,45 @@
+#!/bin/sh
+# Generates the assembly for the .symbol_table section from a linked kernel image.
+#
+# Usage: symbol_table.sh kernel.bin > symbol_table.S
+#
+# Layout (little endian), read by src/debug/symbols.rs:
+#   u64 count
+#   count * { u64 address, u32 name offset, u32 name length }, sorted by address
+#   demangled names, not NUL terminated
+
+set -e
+
+nm --defined-only --numeric-sort --demangle "$1" | LC_ALL=C awk '
+  BEGIN {
+    count = 0
+  }
+
+  # Only code linked in the higher half, the 32-bit boot code never shows up in a backtrace.
+  $2 ~ /^[tTwW]$/ && $1 >= "ffffffff80000000" {
+    # Demangled names may contain spaces, e.g. `<T as core::fmt::Write>::write_str`.
+    name = $0
+    sub(/^[^ ]+ [^ ]+ /, "", name)
+
+    addresses[count] = $1
+    names[count] = name
+    count++
+  }
+
+  END {
+    print ".section .symbol_table, \"a\", @progbits"
+    print ".balign 8"
+    printf ".quad %d\n", count
+
+    offset = 0
+
+    for (i = 0; i < count; i++) {
+      printf ".quad 0x%s\n.long %d, %d\n", addresses[i], offset, length(names[i])
+      offset += length(names[i])
+    }
+
+    for (i = 0; i < count; i++) {
+      printf ".ascii \"%s\"\n", names[i]
+    }
+  }
+'
@@ -0,0 +1,90 @@
+use super::{read_u16, read_u32, read_u64, read_u8, sdt::Sdt, GenericAddress};
+
+const FLAG_TIMER_VALUE_EXTENDED: u32 = 1 << 8;
+const FLAG_RESET_REGISTER_SUPPORTED: u32 = 1 << 10;
+
+pub const BOOT_ARCHITECTURE_LEGACY_DEVICES: u16 = 1 << 0;
+pub const BOOT_ARCHITECTURE_8042: u16 = 1 << 1;
+pub const BOOT_ARCHITECTURE_VGA_NOT_PRESENT: u16 = 1 << 2;
+pub const BOOT_ARCHITECTURE_CMOS_RTC_NOT_PRESENT: u16 = 1 << 5;
+
+// Offsets from the start of the table, as in the specification.
+const OFFSET_EXTENDED_ADDRESSES: usize = 148;
+const OFFSET_RESET_REGISTER: usize = 116;
+
+/// Fixed ACPI Description Table, describes the fixed hardware registers.
+#[derive(Debug, Clone, Copy)]
+pub struct Fadt {
+    pub revision: u8,
+    pub dsdt_address: u64,
+    /// ISA interrupt of the System Control Interrupt.
+    pub sci_interrupt: u16,
+    pub smi_command_port: u32,
+    pub acpi_enable: u8,
+    pub acpi_disable: u8,
+    pub pm1a_event_block: Option<GenericAddress>,
+    pub pm1b_event_block: Option<GenericAddress>,
+    pub pm1a_control_block: Option<GenericAddress>,
+    pub pm1b_control_block: Option<GenericAddress>,
+    pub pm2_control_block: Option<GenericAddress>,
+    pub pm_timer_block: Option<GenericAddress>,
+    /// The ACPI PM timer runs at 3.579545 MHz and is either 24 or 32 bits wide.
+    pub pm_timer_is_32_bit: bool,
+    /// CMOS RAM index of the RTC century, 0 if the RTC has none.
+    pub century_register: u8,
+    /// `BOOT_ARCHITECTURE_*` flags (IA-PC boot architecture flags).
+    pub boot_architecture_flags: u16,
+    pub flags: u32,
+    pub reset_register: Option<GenericAddress>,
+    pub reset_value: u8,
+}
+
+impl Fadt {
+    pub const SIGNATURE: &'static [u8; 4] = b"FACP";
+
+    pub fn parse(sdt: &Sdt) -> Option<Self> {
+        let bytes = sdt.bytes();
+        let flags = read_u32(bytes, 112).unwrap_or(0);
+
+        // ACPI 1.0 tables end before the 64-bit fields, and even newer firmware may leave them
+        // zeroed, in which case the 32-bit I/O port blocks apply.
+        let block = |legacy_offset: usize, length_offset: usize, extended_index: usize| {
+            let extended_offset = OFFSET_EXTENDED_ADDRESSES + extended_index * GenericAddress::SIZE;
+
+            GenericAddress::parse(bytes, extended_offset).or_else(|| {
+                let port = read_u32(bytes, legacy_offset).filter(|&port| port != 0)?;
+
+                Some(GenericAddress::io_port(
+                    port,
+                    read_u8(bytes, length_offset)?,
+                ))
+            })
+        };
+
+        let dsdt_address = read_u64(bytes, 140)
+            .filter(|&address| address != 0)
+            .or_else(|| read_u32(bytes, 40).map(|address| address as u64))?;
+
+        Some(Self {
+            revision: sdt.header.revision,
+            dsdt_address,
+            sci_interrupt: read_u16(bytes, 46)?,
+            smi_command_port: read_u32(bytes, 48)?,
+            acpi_enable: read_u8(bytes, 52)?,
+            acpi_disable: read_u8(bytes, 53)?,
+            pm1a_event_block: block(56, 88, 0),
+            pm1b_event_block: block(60, 88, 1),
+            pm1a_control_block: block(64, 89, 2),
+            pm1b_control_block: block(68, 89, 3),
+            pm2_control_block: block(72, 90, 4),
+            pm_timer_block: block(76, 91, 5),
+            pm_timer_is_32_bit: flags & FLAG_TIMER_VALUE_EXTENDED != 0,
+            century_register: read_u8(bytes, 108).unwrap_or(0),
+            boot_architecture_flags: read_u16(bytes, 109).unwrap_or(0),
+            flags,
+            reset_register: GenericAddress::parse(bytes, OFFSET_RESET_REGISTER)
+                .filter(|_| flags & FLAG_RESET_REGISTER_SUPPORTED != 0),
+            reset_value: read_u8(bytes, 128).unwrap_or(0),
+        })
+    }
+}
@@ -0,0 +1,36 @@
+use super::{read_u16, read_u32, sdt::Sdt, GenericAddress};
+
+/// HPET Description Table, locates the High Precision Event Timer.
+#[derive(Debug, Clone, Copy)]
+pub struct Hpet {
+    pub hardware_revision: u8,
+    pub comparator_count: u8,
+    pub counter_is_64_bit: bool,
+    /// Whether the HPET can replace the PIT and RTC interrupts (IRQ 0 and 8).
+    pub legacy_replacement_capable: bool,
+    pub pci_vendor_id: u16,
+    pub base_address: GenericAddress,
+    pub number: u8,
+    /// Minimum main counter ticks between two periodic interrupts.
+    pub minimum_tick: u16,
+}
+
+impl Hpet {
+    pub const SIGNATURE: &'static [u8; 4] = b"HPET";
+
+    pub fn parse(sdt: &Sdt) -> Option<Self> {
+        let data = sdt.data();
+        let event_timer_block_id = read_u32(data, 0)?;
+
+        Some(Self {
+            hardware_revision: event_timer_block_id as u8,
+            comparator_count: ((event_timer_block_id >> 8) & 0x1F) as u8 + 1,
+            counter_is_64_bit: event_timer_block_id & (1 << 13) != 0,
+            legacy_replacement_capable: event_timer_block_id & (1 << 15) != 0,
+            pci_vendor_id: (event_timer_block_id >> 16) as u16,
+            base_address: GenericAddress::parse(data, 4)?,
+            number: *data.get(16)?,
+            minimum_tick: read_u16(data, 17)?,
+        })
+    }
+}
@@ -0,0 +1,237 @@
+use crate::arch::x86_64::io_apic::{
+    decode_mps_inti_flags, InterruptSourceOverride, IoApicInfo, Polarity, TriggerMode,
+};
+
+use super::{read_u16, read_u32, read_u64, sdt::Sdt};
+
+const PCAT_COMPAT: u32 = 1 << 0;
+
+const ENTRY_LOCAL_APIC: u8 = 0;
+const ENTRY_IO_APIC: u8 = 1;
+const ENTRY_INTERRUPT_SOURCE_OVERRIDE: u8 = 2;
+const ENTRY_LOCAL_APIC_NMI: u8 = 4;
+const ENTRY_LOCAL_APIC_ADDRESS_OVERRIDE: u8 = 5;
+const ENTRY_LOCAL_X2APIC: u8 = 9;
+const ENTRY_LOCAL_X2APIC_NMI: u8 = 10;
+
+const PROCESSOR_ENABLED: u32 = 1 << 0;
+const PROCESSOR_ONLINE_CAPABLE: u32 = 1 << 1;
+
+/// Multiple APIC Description Table, lists the interrupt controllers and CPUs.
+#[derive(Clone, Copy)]
+pub struct Madt<'a> {
+    pub local_apic_address: u32,
+    pub flags: u32,
+    entries: &'a [u8],
+}
+
+impl<'a> Madt<'a> {
+    pub const SIGNATURE: &'static [u8; 4] = b"APIC";
+
+    pub fn parse(sdt: &Sdt<'a>) -> Option<Self> {
+        let data = sdt.data();
+
+        Some(Self {
+            local_apic_address: read_u32(data, 0)?,
+            flags: read_u32(data, 4)?,
+            entries: data.get(8..)?,
+        })
+    }
+
+    /// Whether the machine also has 8259 PICs, which must be masked before using the APICs.
+    pub fn has_legacy_pics(&self) -> bool {
+        self.flags & PCAT_COMPAT != 0
+    }
+
+    pub fn entries(&self) -> MadtEntryIter<'a> {
+        MadtEntryIter {
+            bytes: self.entries,
+            offset: 0,
+        }
+    }
+
+    /// Physical address of the local APICs, taking a 64-bit override into account.
+    pub fn local_apic_address(&self) -> u64 {
+        self.entries()
+            .find_map(|entry| match entry {
+                MadtEntry::LocalApicAddressOverride(address) => Some(address),
+                _ => None,
+            })
+            .unwrap_or(self.local_apic_address as u64)
+    }
+
+    pub fn processors(&self) -> impl Iterator<Item = Processor> + Clone + 'a {
+        self.entries().filter_map(|entry| match entry {
+            MadtEntry::Processor(processor) => Some(processor),
+            _ => None,
+        })
+    }
+
+    pub fn io_apics(&self) -> impl Iterator<Item = IoApicInfo> + Clone + 'a {
+        self.entries().filter_map(|entry| match entry {
+            MadtEntry::IoApic(io_apic) => Some(io_apic),
+            _ => None,
+        })
+    }
+
+    pub fn interrupt_source_overrides(
+        &self,
+    ) -> impl Iterator<Item = InterruptSourceOverride> + Clone + 'a {
+        self.entries().filter_map(|entry| match entry {
+            MadtEntry::InterruptSourceOverride(interrupt_source_override) => {
+                Some(interrupt_source_override)
+            }
+            _ => None,
+        })
+    }
+}
+
+/// A CPU, described by either a local APIC or a local x2APIC entry.
+#[derive(Debug, Clone, Copy)]
+pub struct Processor {
+    pub processor_uid: u32,
+    pub apic_id: u32,
+    pub enabled: bool,
+    /// A disabled processor that can be brought online later.
+    pub online_capable: bool,
+}
+
+impl Processor {
+    fn new(processor_uid: u32, apic_id: u32, flags: u32) -> Self {
+        Self {
+            processor_uid,
+            apic_id,
+            enabled: flags & PROCESSOR_ENABLED != 0,
+            online_capable: flags & PROCESSOR_ONLINE_CAPABLE != 0,
+        }
+    }
+}
+
+/// Local APIC input wired to the NMI.
+#[derive(Debug, Clone, Copy)]
+pub struct LocalApicNmi {
+    /// `None` when it applies to all processors.
+    pub processor_uid: Option<u32>,
+    pub polarity: Polarity,
+    pub trigger_mode: TriggerMode,
+    pub lint: u8,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum MadtEntry<'a> {
+    Processor(Processor),
+    IoApic(IoApicInfo),
+    InterruptSourceOverride(InterruptSourceOverride),
+    LocalApicNmi(LocalApicNmi),
+    LocalApicAddressOverride(u64),
+    Unknown { entry_type: u8, data: &'a [u8] },
+}
+
+impl<'a> MadtEntry<'a> {
+    fn parse(entry_type: u8, data: &'a [u8]) -> Self {
+        let entry = match entry_type {
+            ENTRY_LOCAL_APIC => parse_local_apic(data),
+            ENTRY_IO_APIC => parse_io_apic(data),
+            ENTRY_INTERRUPT_SOURCE_OVERRIDE => parse_interrupt_source_override(data),
+            ENTRY_LOCAL_APIC_NMI => parse_local_apic_nmi(data),
+            ENTRY_LOCAL_APIC_ADDRESS_OVERRIDE => {
+                read_u64(data, 2).map(MadtEntry::LocalApicAddressOverride)
+            }
+            ENTRY_LOCAL_X2APIC => parse_local_x2apic(data),
+            ENTRY_LOCAL_X2APIC_NMI => parse_local_x2apic_nmi(data),
+            _ => None,
+        };
+
+        // Truncated entries are handed out raw, like unknown ones.
+        entry.unwrap_or(MadtEntry::Unknown { entry_type, data })
+    }
+}
+
+// The offsets below are relative to the end of the type and length bytes.
+
+fn parse_local_apic(data: &[u8]) -> Option<MadtEntry<'_>> {
+    Some(MadtEntry::Processor(Processor::new(
+        *data.first()? as u32,
+        *data.get(1)? as u32,
+        read_u32(data, 2)?,
+    )))
+}
+
+fn parse_io_apic(data: &[u8]) -> Option<MadtEntry<'_>> {
+    Some(MadtEntry::IoApic(IoApicInfo {
+        id: *data.first()?,
+        address: read_u32(data, 2)?,
+        gsi_base: read_u32(data, 6)?,
+    }))
+}
+
+fn parse_interrupt_source_override(data: &[u8]) -> Option<MadtEntry<'_>> {
+    let (polarity, trigger_mode) = decode_mps_inti_flags(read_u16(data, 6)?);
+
+    Some(MadtEntry::InterruptSourceOverride(
+        InterruptSourceOverride {
+            source: *data.get(1)?,
+            gsi: read_u32(data, 2)?,
+            polarity,
+            trigger_mode,
+        },
+    ))
+}
+
+fn parse_local_apic_nmi(data: &[u8]) -> Option<MadtEntry<'_>> {
+    let processor_uid = *data.first()?;
+    let (polarity, trigger_mode) = decode_mps_inti_flags(read_u16(data, 1)?);
+
+    Some(MadtEntry::LocalApicNmi(LocalApicNmi {
+        processor_uid: (processor_uid != 0xFF).then_some(processor_uid as u32),
+        polarity,
+        trigger_mode,
+        lint: *data.get(3)?,
+    }))
+}
+
+fn parse_local_x2apic(data: &[u8]) -> Option<MadtEntry<'_>> {
+    Some(MadtEntry::Processor(Processor::new(
+        read_u32(data, 10)?,
+        read_u32(data, 2)?,
+        read_u32(data, 6)?,
+    )))
+}
+
+fn parse_local_x2apic_nmi(data: &[u8]) -> Option<MadtEntry<'_>> {
+    let processor_uid = read_u32(data, 2)?;
+    let (polarity, trigger_mode) = decode_mps_inti_flags(read_u16(data, 0)?);
+
+    Some(MadtEntry::LocalApicNmi(LocalApicNmi {
+        processor_uid: (processor_uid != u32::MAX).then_some(processor_uid),
+        polarity,
+        trigger_mode,
+        lint: *data.get(6)?,
+    }))
+}
+
+#[derive(Clone)]
+pub struct MadtEntryIter<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Iterator for MadtEntryIter<'a> {
+    type Item = MadtEntry<'a>;
+
+    fn next(&mut self) -> Option<MadtEntry<'a>> {
+        let entry_type = *self.bytes.get(self.offset)?;
+        let length = *self.bytes.get(self.offset + 1)? as usize;
+
+        // Every entry starts with its type and length, anything shorter would loop forever.
+        if length < 2 {
+            return None;
+        }
+
+        let data = self.bytes.get(self.offset + 2..self.offset + length)?;
+
+        self.offset += length;
+
+        Some(MadtEntry::parse(entry_type, data))
+    }
+}
@@ -0,0 +1,58 @@
+use super::{read_u16, read_u64, sdt::Sdt};
+
+const ENTRY_SIZE: usize = 16;
+
+/// PCI Express memory mapped configuration space base address table.
+#[derive(Clone, Copy)]
+pub struct Mcfg<'a> {
+    entries: &'a [u8],
+}
+
+impl<'a> Mcfg<'a> {
+    pub const SIGNATURE: &'static [u8; 4] = b"MCFG";
+
+    pub fn parse(sdt: &Sdt<'a>) -> Self {
+        // 8 reserved bytes precede the entries.
+        Self {
+            entries: sdt.data().get(8..).unwrap_or(&[]),
+        }
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = McfgEntry> + 'a {
+        self.entries
+            .chunks_exact(ENTRY_SIZE)
+            .filter_map(McfgEntry::parse)
+    }
+}
+
+/// Configuration space of the buses `start_bus..=end_bus` of a PCI segment group.
+#[derive(Debug, Clone, Copy)]
+pub struct McfgEntry {
+    pub base_address: u64,
+    pub segment_group: u16,
+    pub start_bus: u8,
+    pub end_bus: u8,
+}
+
+impl McfgEntry {
+    fn parse(bytes: &[u8]) -> Option<Self> {
+        Some(Self {
+            base_address: read_u64(bytes, 0)?,
+            segment_group: read_u16(bytes, 8)?,
+            start_bus: *bytes.get(10)?,
+            end_bus: *bytes.get(11)?,
+        })
+    }
+
+    /// Physical address of the 4 KiB configuration space of a function.
+    pub fn configuration_address(&self, bus: u8, device: u8, function: u8) -> Option<u64> {
+        if bus < self.start_bus || bus > self.end_bus || device >= 32 || function >= 8 {
+            return None;
+        }
+
+        let offset =
+            ((bus - self.start_bus) as u64) << 20 | (device as u64) << 15 | (function as u64) << 12;
+
+        Some(self.base_address + offset)
+    }
+}
@@ -0,0 +1,223 @@
+//! Static ACPI tables, i.e. the ones that can be used without an AML interpreter.
+
+pub mod fadt;
+pub mod hpet;
+pub mod madt;
+pub mod mcfg;
+pub mod rsdp;
+pub mod sdt;
+
+use core::fmt;
+
+use spin::Once;
+
+use crate::boot::multiboot2::BootInformation;
+
+use self::{fadt::Fadt, hpet::Hpet, madt::Madt, mcfg::Mcfg, rsdp::Rsdp, sdt::Sdt};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Signature(pub [u8; 4]);
+
+impl fmt::Display for Signature {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0 {
+            let character = if byte.is_ascii_graphic() {
+                byte as char
+            } else {
+                '?'
+            };
+
+            write!(formatter, "{}", character)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Signature {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(formatter, "\"{}\"", self)
+    }
+}
+
+#[derive(Debug)]
+pub enum AcpiError {
+    RsdpNotFound,
+    InvalidChecksum(Signature),
+    /// The table is shorter than its fixed fields.
+    Truncated(Signature),
+    TableNotFound(Signature),
+    /// The table lies beyond the direct map of physical memory.
+    Unreachable(u64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressSpace {
+    SystemMemory,
+    SystemIo,
+    PciConfiguration,
+    Other(u8),
+}
+
+/// Generic Address Structure, how ACPI describes the location of a register.
+#[derive(Debug, Clone, Copy)]
+pub struct GenericAddress {
+    pub address_space: AddressSpace,
+    pub bit_width: u8,
+    pub bit_offset: u8,
+    pub access_size: u8,
+    pub address: u64,
+}
+
+impl GenericAddress {
+    pub const SIZE: usize = 12;
+
+    /// Parses the structure at `offset`, `None` if it is missing or zeroed (not implemented).
+    fn parse(bytes: &[u8], offset: usize) -> Option<Self> {
+        let address = read_u64(bytes, offset + 4)?;
+
+        if address == 0 {
+            return None;
+        }
+
+        let address_space = match read_u8(bytes, offset)? {
+            0 => AddressSpace::SystemMemory,
+            1 => AddressSpace::SystemIo,
+            2 => AddressSpace::PciConfiguration,
+            other => AddressSpace::Other(other),
+        };
+
+        Some(Self {
+            address_space,
+            bit_width: read_u8(bytes, offset + 1)?,
+            bit_offset: read_u8(bytes, offset + 2)?,
+            access_size: read_u8(bytes, offset + 3)?,
+            address,
+        })
+    }
+
+    const fn io_port(port: u32, length: u8) -> Self {
+        Self {
+            address_space: AddressSpace::SystemIo,
+            bit_width: length * 8,
+            bit_offset: 0,
+            access_size: 0,
+            address: port as u64,
+        }
+    }
+}
+
+/// The root table (XSDT, or RSDT on ACPI 1.0 firmware) and the RSDP that led to it.
+pub struct AcpiTables {
+    pub rsdp: Rsdp,
+    pub root: Sdt<'static>,
+}
+
+impl AcpiTables {
+    /// # Safety
+    ///
+    /// `rsdp` must point to valid ACPI tables.
+    pub unsafe fn load(rsdp: Rsdp) -> Result<Self, AcpiError> {
+        let root = match rsdp.xsdt_address {
+            Some(xsdt_address) => Sdt::load(xsdt_address)?,
+            None => Sdt::load(rsdp.rsdt_address as u64)?,
+        };
+
+        Ok(Self { rsdp, root })
+    }
+
+    /// Physical addresses of the tables listed by the root table.
+    pub fn addresses(&self) -> impl Iterator<Item = u64> + '_ {
+        let is_xsdt = self.root.header.signature.0 == *b"XSDT";
+        let entry_size = if is_xsdt { 8 } else { 4 };
+
+        self.root
+            .data()
+            .chunks_exact(entry_size)
+            .map(move |entry| match is_xsdt {
+                true => u64::from_le_bytes(entry.try_into().unwrap()),
+                false => u32::from_le_bytes(entry.try_into().unwrap()) as u64,
+            })
+    }
+
+    /// Tables listed by the root table. Tables that cannot be reached or fail their checksum are
+    /// skipped.
+    pub fn tables(&self) -> impl Iterator<Item = Sdt<'static>> + '_ {
+        self.addresses()
+            .filter_map(|address| unsafe { Sdt::load(address) }.ok())
+    }
+
+    pub fn find(&self, signature: &[u8; 4]) -> Result<Sdt<'static>, AcpiError> {
+        for address in self.addresses() {
+            if unsafe { sdt::peek_signature(address) } == Some(Signature(*signature)) {
+                return unsafe { Sdt::load(address) };
+            }
+        }
+
+        Err(AcpiError::TableNotFound(Signature(*signature)))
+    }
+
+    pub fn madt(&self) -> Result<Madt<'static>, AcpiError> {
+        let sdt = self.find(Madt::SIGNATURE)?;
+
+        Madt::parse(&sdt).ok_or(AcpiError::Truncated(sdt.header.signature))
+    }
+
+    pub fn fadt(&self) -> Result<Fadt, AcpiError> {
+        let sdt = self.find(Fadt::SIGNATURE)?;
+
+        Fadt::parse(&sdt).ok_or(AcpiError::Truncated(sdt.header.signature))
+    }
+
+    pub fn hpet(&self) -> Result<Hpet, AcpiError> {
+        let sdt = self.find(Hpet::SIGNATURE)?;
+
+        Hpet::parse(&sdt).ok_or(AcpiError::Truncated(sdt.header.signature))
+    }
+
+    pub fn mcfg(&self) -> Result<Mcfg<'static>, AcpiError> {
+        let sdt = self.find(Mcfg::SIGNATURE)?;
+
+        Ok(Mcfg::parse(&sdt))
+    }
+}
+
+static TABLES: Once<AcpiTables> = Once::new();
+
+/// Locates the RSDP and the root table. Called once during boot.
+pub fn init(boot_information: &BootInformation) -> Result<&'static AcpiTables, AcpiError> {
+    let rsdp = Rsdp::find(boot_information).ok_or(AcpiError::RsdpNotFound)?;
+    let tables = unsafe { AcpiTables::load(rsdp)? };
+
+    Ok(TABLES.call_once(|| tables))
+}
+
+pub fn tables() -> Option<&'static AcpiTables> {
+    TABLES.r#try()
+}
+
+fn checksum_is_valid(bytes: &[u8]) -> bool {
+    bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) == 0
+}
+
+fn read_u8(bytes: &[u8], offset: usize) -> Option<u8> {
+    bytes.get(offset).copied()
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        bytes.get(offset..offset + 2)?.try_into().unwrap(),
+    ))
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        bytes.get(offset..offset + 4)?.try_into().unwrap(),
+    ))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        bytes.get(offset..offset + 8)?.try_into().unwrap(),
+    ))
+}
@@ -0,0 +1,108 @@
+use crate::{
+    boot::{
+        efi::{self, SystemTable},
+        multiboot2::BootInformation,
+    },
+    memory::layout,
+};
+
+use super::{checksum_is_valid, read_u16, read_u32, read_u64};
+
+const SIGNATURE: &[u8; 8] = b"RSD PTR ";
+const V1_LENGTH: usize = 20;
+
+/// Segment of the Extended BIOS Data Area, stored in the BIOS Data Area.
+const EBDA_SEGMENT_POINTER: u64 = 0x40E;
+const EBDA_SEARCH_LENGTH: usize = 1024;
+const BIOS_AREA_START: u64 = 0xE0000;
+const BIOS_AREA_END: u64 = 0x100000;
+
+/// Root System Description Pointer, the entry point to the ACPI tables.
+#[derive(Debug, Clone, Copy)]
+pub struct Rsdp {
+    pub oem_id: [u8; 6],
+    /// 0 for ACPI 1.0, 2 for ACPI 2.0 and later.
+    pub revision: u8,
+    pub rsdt_address: u32,
+    pub xsdt_address: Option<u64>,
+}
+
+impl Rsdp {
+    /// Parses and validates an RSDP, `bytes` may extend past its end.
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        if bytes.get(0..8)? != SIGNATURE || !checksum_is_valid(bytes.get(..V1_LENGTH)?) {
+            return None;
+        }
+
+        let revision = *bytes.get(15)?;
+        let mut xsdt_address = None;
+
+        if revision >= 2 {
+            let length = read_u32(bytes, 20)? as usize;
+
+            if !checksum_is_valid(bytes.get(..length)?) {
+                return None;
+            }
+
+            xsdt_address = read_u64(bytes, 24).filter(|&address| address != 0);
+        }
+
+        Some(Self {
+            oem_id: bytes.get(9..15)?.try_into().unwrap(),
+            revision,
+            rsdt_address: read_u32(bytes, 16)?,
+            xsdt_address,
+        })
+    }
+
+    /// Takes the copy handed over by the boot loader, then the one published in the EFI
+    /// configuration table. On BIOS machines, falls back to the legacy search (first KiB of the
+    /// EBDA, then 0xE0000-0xFFFFF, on 16 byte boundaries).
+    pub fn find(boot_information: &BootInformation) -> Option<Self> {
+        if let Some(rsdp) = boot_information
+            .rsdp()
+            .and_then(|rsdp| Self::parse(rsdp.bytes()))
+        {
+            return Some(rsdp);
+        }
+
+        if boot_information.is_efi_boot() {
+            return Self::find_in_efi_system_table(boot_information);
+        }
+
+        let ebda_pointer = unsafe { layout::physical_slice(EBDA_SEGMENT_POINTER, 2) }?;
+        let ebda_start = (read_u16(ebda_pointer, 0)? as u64) << 4;
+
+        if ebda_start != 0 {
+            let ebda = unsafe { layout::physical_slice(ebda_start, EBDA_SEARCH_LENGTH) };
+
+            if let Some(rsdp) = ebda.and_then(Self::scan) {
+                return Some(rsdp);
+            }
+        }
+
+        let bios_area_length = (BIOS_AREA_END - BIOS_AREA_START) as usize;
+
+        Self::scan(unsafe { layout::physical_slice(BIOS_AREA_START, bios_area_length) }?)
+    }
+
+    fn find_in_efi_system_table(boot_information: &BootInformation) -> Option<Self> {
+        let system_table = unsafe { SystemTable::load(boot_information.efi_system_table()?) }?;
+
+        [efi::ACPI_20_TABLE_GUID, efi::ACPI_TABLE_GUID]
+            .iter()
+            .filter_map(|guid| system_table.find_configuration_table(guid))
+            .find_map(|address| {
+                // Only the ACPI 2.0 length is known up front, the checksums reject anything else.
+                let bytes = unsafe { layout::physical_slice(address, 36) }?;
+
+                Self::parse(bytes)
+            })
+    }
+
+    fn scan(area: &[u8]) -> Option<Self> {
+        (0..area.len())
+            .step_by(16)
+            .find_map(|offset| Self::parse(&area[offset..]))
+    }
+}
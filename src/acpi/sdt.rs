@@ -0,0 +1,95 @@
+use crate::memory::layout;
+
+use super::{checksum_is_valid, read_u32, AcpiError, Signature};
+
+/// Header shared by every System Description Table.
+#[derive(Debug, Clone, Copy)]
+pub struct SdtHeader {
+    pub signature: Signature,
+    pub length: u32,
+    pub revision: u8,
+    pub oem_id: [u8; 6],
+    pub oem_table_id: [u8; 8],
+    pub oem_revision: u32,
+    pub creator_id: u32,
+    pub creator_revision: u32,
+}
+
+impl SdtHeader {
+    pub const SIZE: usize = 36;
+
+    fn parse(bytes: &[u8]) -> Option<Self> {
+        Some(Self {
+            signature: Signature(bytes.get(0..4)?.try_into().unwrap()),
+            length: read_u32(bytes, 4)?,
+            revision: *bytes.get(8)?,
+            oem_id: bytes.get(10..16)?.try_into().unwrap(),
+            oem_table_id: bytes.get(16..24)?.try_into().unwrap(),
+            oem_revision: read_u32(bytes, 24)?,
+            creator_id: read_u32(bytes, 28)?,
+            creator_revision: read_u32(bytes, 32)?,
+        })
+    }
+}
+
+/// A System Description Table with a valid checksum.
+#[derive(Clone, Copy)]
+pub struct Sdt<'a> {
+    pub header: SdtHeader,
+    bytes: &'a [u8],
+}
+
+impl<'a> Sdt<'a> {
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, AcpiError> {
+        let signature = Signature(*bytes.first_chunk::<4>().unwrap_or(&[0; 4]));
+        let header = SdtHeader::parse(bytes).ok_or(AcpiError::Truncated(signature))?;
+
+        let bytes = bytes
+            .get(..header.length as usize)
+            .filter(|bytes| bytes.len() >= SdtHeader::SIZE)
+            .ok_or(AcpiError::Truncated(signature))?;
+
+        if !checksum_is_valid(bytes) {
+            return Err(AcpiError::InvalidChecksum(signature));
+        }
+
+        Ok(Self { header, bytes })
+    }
+
+    /// The whole table, header included.
+    pub fn bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// The table after the common header.
+    pub fn data(&self) -> &'a [u8] {
+        &self.bytes[SdtHeader::SIZE..]
+    }
+}
+
+impl Sdt<'static> {
+    /// # Safety
+    ///
+    /// `physical_address` must point to an ACPI table.
+    pub unsafe fn load(physical_address: u64) -> Result<Self, AcpiError> {
+        let header = physical_bytes(physical_address, SdtHeader::SIZE)?;
+        let length = read_u32(header, 4).unwrap() as usize;
+
+        Self::parse(physical_bytes(physical_address, length)?)
+    }
+}
+
+/// Signature of the table at `physical_address`, without validating the rest of it.
+///
+/// # Safety
+///
+/// `physical_address` must point to an ACPI table.
+pub unsafe fn peek_signature(physical_address: u64) -> Option<Signature> {
+    let bytes = physical_bytes(physical_address, 4).ok()?;
+
+    Some(Signature(bytes.try_into().unwrap()))
+}
+
+unsafe fn physical_bytes(physical_address: u64, length: usize) -> Result<&'static [u8], AcpiError> {
+    layout::physical_slice(physical_address, length).ok_or(AcpiError::Unreachable(physical_address))
+}
@@ -1,6 +1,6 @@
-#[cfg(target_arch = "x86")]
+#[cfg(target_arch = "x86_64")]
 #[macro_use]
 pub mod x86_64;
 
-#[cfg(target_arch = "x86")]
+#[cfg(target_arch = "x86_64")]
 pub use x86_64::*;
@@ -0,0 +1,51 @@
+//! Saving and restoring the registers of kernel threads, see context_switch.S.
+
+use core::ptr;
+
+extern "C" {
+    fn context_switch(previous_stack_pointer: *mut u64, next_stack_pointer: u64);
+    fn thread_entry();
+}
+
+/// Entry point of a new thread, it receives the argument given to `prepare_stack`.
+pub type ThreadEntry = extern "C" fn(u64) -> !;
+
+/// Words in the initial frame: R15, R14, R13, R12, RBX and RBP, the return address, and padding
+/// that leaves RSP 16 byte aligned at the call to the entry point. The padding doubles as a null
+/// return address that ends stack traces.
+const INITIAL_FRAME_WORDS: usize = 9;
+const R13_INDEX: usize = 2;
+const R12_INDEX: usize = 3;
+const RETURN_ADDRESS_INDEX: usize = 6;
+
+/// Builds the frame `switch` restores the first time it switches to a new thread. `stack_top`
+/// must be 16 byte aligned. Returns the thread's initial stack pointer.
+///
+/// # Safety
+///
+/// The memory below `stack_top` must be a stack that is not in use.
+pub unsafe fn prepare_stack(stack_top: u64, entry: ThreadEntry, argument: u64) -> u64 {
+    let frame = (stack_top as *mut u64).sub(INITIAL_FRAME_WORDS);
+
+    ptr::write_bytes(frame, 0, INITIAL_FRAME_WORDS);
+
+    frame.add(R13_INDEX).write(argument);
+    frame.add(R12_INDEX).write(entry as *const () as u64);
+    frame
+        .add(RETURN_ADDRESS_INDEX)
+        .write(thread_entry as *const () as u64);
+
+    frame as u64
+}
+
+/// Saves the callee-saved registers and stack pointer of the running thread in
+/// `previous_stack_pointer`, then resumes the thread that saved `next_stack_pointer`.
+///
+/// # Safety
+///
+/// Interrupts must be disabled, and `next_stack_pointer` must come from `prepare_stack` or from
+/// an earlier switch away from a thread that has not run since.
+#[inline(never)]
+pub unsafe fn switch(previous_stack_pointer: *mut u64, next_stack_pointer: u64) {
+    context_switch(previous_stack_pointer, next_stack_pointer);
+}
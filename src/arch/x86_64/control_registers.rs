@@ -0,0 +1,62 @@
+use core::arch::asm;
+
+#[inline]
+pub fn read_cr0() -> u64 {
+    let cr0: u64;
+
+    unsafe {
+        asm!("mov {}, cr0", out(reg) cr0, options(nomem, nostack, preserves_flags));
+    }
+
+    cr0
+}
+
+/// Linear address that caused the last page fault.
+#[inline]
+pub fn read_cr2() -> u64 {
+    let cr2: u64;
+
+    unsafe {
+        asm!("mov {}, cr2", out(reg) cr2, options(nomem, nostack, preserves_flags));
+    }
+
+    cr2
+}
+
+#[inline]
+pub fn read_cr3() -> u64 {
+    let cr3: u64;
+
+    unsafe {
+        asm!("mov {}, cr3", out(reg) cr3, options(nomem, nostack, preserves_flags));
+    }
+
+    cr3
+}
+
+/// # Safety
+///
+/// `page_table` must be the physical address of a PML4 that maps the running code.
+#[inline]
+pub unsafe fn write_cr3(page_table: u64) {
+    asm!("mov cr3, {}", in(reg) page_table, options(nostack, preserves_flags));
+}
+
+#[inline]
+pub fn read_cr4() -> u64 {
+    let cr4: u64;
+
+    unsafe {
+        asm!("mov {}, cr4", out(reg) cr4, options(nomem, nostack, preserves_flags));
+    }
+
+    cr4
+}
+
+/// # Safety
+///
+/// Changing paging or protection bits affects every memory access that follows.
+#[inline]
+pub unsafe fn write_cr4(cr4: u64) {
+    asm!("mov cr4, {}", in(reg) cr4, options(nostack, preserves_flags));
+}
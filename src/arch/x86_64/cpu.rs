@@ -0,0 +1,373 @@
+use core::{
+    arch::{
+        asm,
+        x86_64::{__cpuid, __cpuid_count, __get_cpuid_max},
+    },
+    fmt, str,
+};
+
+use spin::Once;
+
+use crate::arch::x86_64::{
+    control_registers::{read_cr4, write_cr4},
+    msr::{read_msr, write_msr, IA32_EFER},
+};
+
+pub const EFER_NO_EXECUTE_ENABLE: u64 = 1 << 11;
+
+const CR4_UMIP: u64 = 1 << 11;
+const CR4_SMEP: u64 = 1 << 20;
+const CR4_SMAP: u64 = 1 << 21;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Vendor {
+    Intel,
+    Amd,
+    Other,
+}
+
+/// A CPUID feature flag, each one decoded from a single bit of a single leaf.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Feature {
+    Sse,
+    Sse2,
+    Sse3,
+    Ssse3,
+    Sse41,
+    Sse42,
+    Avx,
+    Avx2,
+    Xsave,
+    Apic,
+    X2Apic,
+    Tsc,
+    InvariantTsc,
+    Pge,
+    Pat,
+    Pcid,
+    NoExecute,
+    HugePages1G,
+    Smep,
+    Smap,
+    Umip,
+    FsGsBase,
+    Rdrand,
+    Rdseed,
+    Syscall,
+    Hypervisor,
+}
+
+impl Feature {
+    pub const ALL: [Feature; 26] = [
+        Feature::Sse,
+        Feature::Sse2,
+        Feature::Sse3,
+        Feature::Ssse3,
+        Feature::Sse41,
+        Feature::Sse42,
+        Feature::Avx,
+        Feature::Avx2,
+        Feature::Xsave,
+        Feature::Apic,
+        Feature::X2Apic,
+        Feature::Tsc,
+        Feature::InvariantTsc,
+        Feature::Pge,
+        Feature::Pat,
+        Feature::Pcid,
+        Feature::NoExecute,
+        Feature::HugePages1G,
+        Feature::Smep,
+        Feature::Smap,
+        Feature::Umip,
+        Feature::FsGsBase,
+        Feature::Rdrand,
+        Feature::Rdseed,
+        Feature::Syscall,
+        Feature::Hypervisor,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Feature::Sse => "sse",
+            Feature::Sse2 => "sse2",
+            Feature::Sse3 => "sse3",
+            Feature::Ssse3 => "ssse3",
+            Feature::Sse41 => "sse4.1",
+            Feature::Sse42 => "sse4.2",
+            Feature::Avx => "avx",
+            Feature::Avx2 => "avx2",
+            Feature::Xsave => "xsave",
+            Feature::Apic => "apic",
+            Feature::X2Apic => "x2apic",
+            Feature::Tsc => "tsc",
+            Feature::InvariantTsc => "invariant-tsc",
+            Feature::Pge => "pge",
+            Feature::Pat => "pat",
+            Feature::Pcid => "pcid",
+            Feature::NoExecute => "nx",
+            Feature::HugePages1G => "1g-pages",
+            Feature::Smep => "smep",
+            Feature::Smap => "smap",
+            Feature::Umip => "umip",
+            Feature::FsGsBase => "fsgsbase",
+            Feature::Rdrand => "rdrand",
+            Feature::Rdseed => "rdseed",
+            Feature::Syscall => "syscall",
+            Feature::Hypervisor => "hypervisor",
+        }
+    }
+
+    /// The leaf, register and bit the feature is reported in.
+    fn location(self) -> (u32, Register, u32) {
+        match self {
+            Feature::Sse => (1, Register::Edx, 25),
+            Feature::Sse2 => (1, Register::Edx, 26),
+            Feature::Sse3 => (1, Register::Ecx, 0),
+            Feature::Ssse3 => (1, Register::Ecx, 9),
+            Feature::Sse41 => (1, Register::Ecx, 19),
+            Feature::Sse42 => (1, Register::Ecx, 20),
+            Feature::Avx => (1, Register::Ecx, 28),
+            Feature::Avx2 => (7, Register::Ebx, 5),
+            Feature::Xsave => (1, Register::Ecx, 26),
+            Feature::Apic => (1, Register::Edx, 9),
+            Feature::X2Apic => (1, Register::Ecx, 21),
+            Feature::Tsc => (1, Register::Edx, 4),
+            Feature::InvariantTsc => (0x8000_0007, Register::Edx, 8),
+            Feature::Pge => (1, Register::Edx, 13),
+            Feature::Pat => (1, Register::Edx, 16),
+            Feature::Pcid => (1, Register::Ecx, 17),
+            Feature::NoExecute => (0x8000_0001, Register::Edx, 20),
+            Feature::HugePages1G => (0x8000_0001, Register::Edx, 26),
+            Feature::Smep => (7, Register::Ebx, 7),
+            Feature::Smap => (7, Register::Ebx, 20),
+            Feature::Umip => (7, Register::Ecx, 2),
+            Feature::FsGsBase => (7, Register::Ebx, 0),
+            Feature::Rdrand => (1, Register::Ecx, 30),
+            Feature::Rdseed => (7, Register::Ebx, 18),
+            Feature::Syscall => (0x8000_0001, Register::Edx, 11),
+            Feature::Hypervisor => (1, Register::Ecx, 31),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Register {
+    Ebx,
+    Ecx,
+    Edx,
+}
+
+/// A set of [`Feature`]s, one bit per variant.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub struct Features(u64);
+
+impl Features {
+    pub fn contains(&self, feature: Feature) -> bool {
+        self.0 & (1 << feature as u8) != 0
+    }
+
+    fn insert(&mut self, feature: Feature) {
+        self.0 |= 1 << feature as u8;
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Feature> + '_ {
+        Feature::ALL
+            .iter()
+            .copied()
+            .filter(|&feature| self.contains(feature))
+    }
+}
+
+impl fmt::Debug for Features {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.debug_set().entries(self.iter()).finish()
+    }
+}
+
+/// Identification and features of the boot CPU. Application processors are assumed to match it.
+#[derive(Debug, Clone, Copy)]
+pub struct CpuInfo {
+    pub vendor: Vendor,
+    vendor_id: [u8; 12],
+    brand: [u8; 48],
+    pub family: u32,
+    pub model: u32,
+    pub stepping: u32,
+    pub features: Features,
+}
+
+impl CpuInfo {
+    fn detect() -> Self {
+        let (max_leaf, _) = __get_cpuid_max(0);
+        let (max_extended_leaf, _) = __get_cpuid_max(0x8000_0000);
+
+        let leaf = |leaf: u32| {
+            let available = if leaf >= 0x8000_0000 {
+                leaf <= max_extended_leaf
+            } else {
+                leaf <= max_leaf
+            };
+
+            available.then(|| __cpuid_count(leaf, 0))
+        };
+
+        let vendor_leaf = __cpuid(0);
+        let mut vendor_id = [0; 12];
+
+        vendor_id[0..4].copy_from_slice(&vendor_leaf.ebx.to_le_bytes());
+        vendor_id[4..8].copy_from_slice(&vendor_leaf.edx.to_le_bytes());
+        vendor_id[8..12].copy_from_slice(&vendor_leaf.ecx.to_le_bytes());
+
+        let vendor = match &vendor_id {
+            b"GenuineIntel" => Vendor::Intel,
+            b"AuthenticAMD" => Vendor::Amd,
+            _ => Vendor::Other,
+        };
+
+        // The extended family and model fields only apply to some base families.
+        let signature = __cpuid(1).eax;
+        let base_family = (signature >> 8) & 0xF;
+        let base_model = (signature >> 4) & 0xF;
+
+        let family = if base_family == 0xF {
+            base_family + ((signature >> 20) & 0xFF)
+        } else {
+            base_family
+        };
+
+        let model = if base_family == 0x6 || base_family == 0xF {
+            base_model | ((signature >> 16) & 0xF) << 4
+        } else {
+            base_model
+        };
+
+        let mut brand = [0; 48];
+
+        if max_extended_leaf >= 0x8000_0004 {
+            for (index, chunk) in brand.chunks_exact_mut(16).enumerate() {
+                let result = __cpuid(0x8000_0002 + index as u32);
+
+                chunk[0..4].copy_from_slice(&result.eax.to_le_bytes());
+                chunk[4..8].copy_from_slice(&result.ebx.to_le_bytes());
+                chunk[8..12].copy_from_slice(&result.ecx.to_le_bytes());
+                chunk[12..16].copy_from_slice(&result.edx.to_le_bytes());
+            }
+        }
+
+        let mut features = Features::default();
+
+        for feature in Feature::ALL {
+            let (leaf_number, register, bit) = feature.location();
+
+            let Some(result) = leaf(leaf_number) else {
+                continue;
+            };
+
+            let value = match register {
+                Register::Ebx => result.ebx,
+                Register::Ecx => result.ecx,
+                Register::Edx => result.edx,
+            };
+
+            if value & (1 << bit) != 0 {
+                features.insert(feature);
+            }
+        }
+
+        Self {
+            vendor,
+            vendor_id,
+            brand,
+            family,
+            model,
+            stepping: signature & 0xF,
+            features,
+        }
+    }
+
+    pub fn vendor_id(&self) -> &str {
+        str::from_utf8(&self.vendor_id).unwrap_or("")
+    }
+
+    /// Processor name string, empty when the CPU does not report one.
+    pub fn brand(&self) -> &str {
+        let length = self
+            .brand
+            .iter()
+            .position(|&byte| byte == 0)
+            .unwrap_or(self.brand.len());
+
+        str::from_utf8(&self.brand[..length]).unwrap_or("").trim()
+    }
+
+    pub fn has(&self, feature: Feature) -> bool {
+        self.features.contains(feature)
+    }
+}
+
+static INFO: Once<CpuInfo> = Once::new();
+
+/// Decoded on first use, so it is available to code that runs before [`enable_features`].
+pub fn info() -> &'static CpuInfo {
+    INFO.call_once(CpuInfo::detect)
+}
+
+pub fn has(feature: Feature) -> bool {
+    info().has(feature)
+}
+
+/// Runs `f` with SMAP checks suspended, so it can read or write user pages. Does nothing special
+/// when SMAP is off.
+pub fn with_user_access<F, R>(f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    let smap = read_cr4() & CR4_SMAP != 0;
+
+    if smap {
+        unsafe { asm!("stac", options(nostack)) };
+    }
+
+    let result = f();
+
+    if smap {
+        unsafe { asm!("clac", options(nostack)) };
+    }
+
+    result
+}
+
+/// Turns on the protections the calling CPU supports: no-execute pages, SMEP, SMAP and UMIP.
+/// Returns the ones that were enabled.
+///
+/// With SMAP on, supervisor accesses to user pages fault outside of [`with_user_access`].
+pub fn enable_features() -> Features {
+    let info = info();
+    let mut enabled = Features::default();
+
+    if info.has(Feature::NoExecute) {
+        unsafe { write_msr(IA32_EFER, read_msr(IA32_EFER) | EFER_NO_EXECUTE_ENABLE) };
+
+        enabled.insert(Feature::NoExecute);
+    }
+
+    let mut cr4 = read_cr4();
+
+    for (feature, bit) in [
+        (Feature::Smep, CR4_SMEP),
+        (Feature::Smap, CR4_SMAP),
+        (Feature::Umip, CR4_UMIP),
+    ] {
+        if info.has(feature) {
+            cr4 |= bit;
+
+            enabled.insert(feature);
+        }
+    }
+
+    unsafe { write_cr4(cr4) };
+
+    enabled
+}
@@ -0,0 +1,335 @@
+use core::{
+    arch::asm,
+    fmt::{self, Write},
+    ops::Range,
+    panic::PanicInfo,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::{
+    arch::x86_64::{
+        control_registers::{read_cr0, read_cr2, read_cr3, read_cr4},
+        idt::{ExceptionErrorCode, InterruptContext, Registers, EXCEPTION_NAMES},
+        interrupts, serial,
+    },
+    debug::symbols,
+    device::vga::VGA_SCREEN,
+    memory::layout::KERNEL_VIRTUAL_BASE,
+    thread,
+};
+
+const CRASH_COLOR: u8 = 0x4F; // White on red
+
+/// Keeps most reports within the 25 lines of the VGA text mode, long symbol names may still wrap.
+const MAX_BACKTRACE_DEPTH: usize = 10;
+
+static CRASHING: AtomicBool = AtomicBool::new(false);
+
+/// CPU state at the moment of the crash.
+struct CrashState {
+    registers: Registers,
+    instruction_pointer: u64,
+    stack_pointer: u64,
+    rflags: u64,
+    code_segment_selector: u64,
+    stack_segment_selector: u64,
+}
+
+impl CrashState {
+    fn from_interrupt(context: &InterruptContext) -> Self {
+        Self {
+            registers: context.registers,
+            instruction_pointer: context.frame.instruction_pointer,
+            stack_pointer: context.frame.stack_pointer,
+            rflags: context.frame.rflags,
+            code_segment_selector: context.frame.code_segment_selector,
+            stack_segment_selector: context.frame.stack_segment_selector,
+        }
+    }
+
+    #[inline(always)]
+    fn capture() -> Self {
+        let mut registers = Registers {
+            r15: 0,
+            r14: 0,
+            r13: 0,
+            r12: 0,
+            r11: 0,
+            r10: 0,
+            r9: 0,
+            r8: 0,
+            rbp: 0,
+            rdi: 0,
+            rsi: 0,
+            rdx: 0,
+            rcx: 0,
+            rbx: 0,
+            rax: 0,
+        };
+        let instruction_pointer: u64;
+        let stack_pointer: u64;
+        let code_segment_selector: u64;
+        let stack_segment_selector: u64;
+
+        unsafe {
+            asm!(
+                "mov [{registers} + 0x00], r15",
+                "mov [{registers} + 0x08], r14",
+                "mov [{registers} + 0x10], r13",
+                "mov [{registers} + 0x18], r12",
+                "mov [{registers} + 0x20], r11",
+                "mov [{registers} + 0x28], r10",
+                "mov [{registers} + 0x30], r9",
+                "mov [{registers} + 0x38], r8",
+                "mov [{registers} + 0x40], rbp",
+                "mov [{registers} + 0x48], rdi",
+                "mov [{registers} + 0x50], rsi",
+                "mov [{registers} + 0x58], rdx",
+                "mov [{registers} + 0x60], rcx",
+                "mov [{registers} + 0x68], rbx",
+                "mov [{registers} + 0x70], rax",
+                registers = in(reg) &mut registers as *mut Registers,
+                options(nostack, preserves_flags)
+            );
+            asm!(
+                "lea {instruction_pointer}, [rip]",
+                "mov {stack_pointer}, rsp",
+                "mov {code_segment_selector:x}, cs",
+                "movzx {code_segment_selector}, {code_segment_selector:x}",
+                "mov {stack_segment_selector:x}, ss",
+                "movzx {stack_segment_selector}, {stack_segment_selector:x}",
+                instruction_pointer = out(reg) instruction_pointer,
+                stack_pointer = out(reg) stack_pointer,
+                code_segment_selector = out(reg) code_segment_selector,
+                stack_segment_selector = out(reg) stack_segment_selector,
+                options(nomem, nostack, preserves_flags)
+            );
+        }
+
+        Self {
+            registers,
+            instruction_pointer,
+            stack_pointer,
+            rflags: interrupts::read_rflags(),
+            code_segment_selector,
+            stack_segment_selector,
+        }
+    }
+}
+
+/// Writes to COM1 and the VGA text buffer at once, ignoring the console selected on the command line.
+struct CrashConsole;
+
+impl fmt::Write for CrashConsole {
+    fn write_str(&mut self, str: &str) -> fmt::Result {
+        serial::write_string(str);
+        VGA_SCREEN.lock().write_string(str);
+
+        Ok(())
+    }
+}
+
+pub fn panic(info: &PanicInfo) -> ! {
+    let state = CrashState::capture();
+
+    report(&state, |console| {
+        write!(console, "KERNEL PANIC: {}", info.message())?;
+
+        if let Some(location) = info.location() {
+            write!(
+                console,
+                "\n  at {}:{}:{}",
+                location.file(),
+                location.line(),
+                location.column()
+            )?;
+        }
+
+        Ok(())
+    })
+}
+
+pub fn exception(context: &InterruptContext) -> ! {
+    let state = CrashState::from_interrupt(context);
+    let vector = context.vector as u8;
+
+    report(&state, |console| {
+        write!(
+            console,
+            "EXCEPTION: {} (#{})",
+            EXCEPTION_NAMES[vector as usize], vector
+        )?;
+
+        if let Some(error_code) = ExceptionErrorCode::new(vector, context.error_code) {
+            write!(console, "\n  error code {}", error_code)?;
+        }
+
+        Ok(())
+    })
+}
+
+fn report(state: &CrashState, describe: impl FnOnce(&mut CrashConsole) -> fmt::Result) -> ! {
+    interrupts::disable();
+
+    // A fault while reporting would recurse forever, give up on the second one.
+    if CRASHING.swap(true, Ordering::SeqCst) {
+        halt_forever();
+    }
+
+    // Whatever was printing when we crashed will never release the screen.
+    unsafe { VGA_SCREEN.force_unlock() };
+
+    {
+        let mut screen = VGA_SCREEN.lock();
+
+        screen.clear_screen();
+        screen.set_color(CRASH_COLOR);
+    }
+
+    let mut console = CrashConsole;
+
+    let _ = describe(&mut console);
+    let _ = write!(console, "\n\n");
+    let _ = dump_registers(&mut console, state);
+    let _ = writeln!(console);
+    let _ = print_backtrace(
+        &mut console,
+        state.instruction_pointer,
+        state.registers.rbp,
+        state.stack_pointer,
+    );
+
+    halt_forever()
+}
+
+fn dump_registers(console: &mut CrashConsole, state: &CrashState) -> fmt::Result {
+    let registers = &state.registers;
+
+    writeln!(
+        console,
+        "RAX={:016x} RBX={:016x} RCX={:016x}",
+        registers.rax, registers.rbx, registers.rcx
+    )?;
+    writeln!(
+        console,
+        "RDX={:016x} RSI={:016x} RDI={:016x}",
+        registers.rdx, registers.rsi, registers.rdi
+    )?;
+    writeln!(
+        console,
+        "RBP={:016x} RSP={:016x} R8 ={:016x}",
+        registers.rbp, state.stack_pointer, registers.r8
+    )?;
+    writeln!(
+        console,
+        "R9 ={:016x} R10={:016x} R11={:016x}",
+        registers.r9, registers.r10, registers.r11
+    )?;
+    writeln!(
+        console,
+        "R12={:016x} R13={:016x} R14={:016x}",
+        registers.r12, registers.r13, registers.r14
+    )?;
+    writeln!(
+        console,
+        "R15={:016x} RIP={:016x} RFLAGS={:08x}",
+        registers.r15, state.instruction_pointer, state.rflags
+    )?;
+    writeln!(
+        console,
+        "CS={:04x} SS={:04x} CR0={:08x} CR2={:016x}",
+        state.code_segment_selector,
+        state.stack_segment_selector,
+        read_cr0(),
+        read_cr2()
+    )?;
+    writeln!(console, "CR3={:016x} CR4={:08x}", read_cr3(), read_cr4())
+}
+
+/// Walks the chain of saved frame pointers (the kernel is built with frame pointers forced on).
+/// Each frame starts with the caller's RBP followed by the return address.
+fn print_backtrace(
+    console: &mut CrashConsole,
+    instruction_pointer: u64,
+    mut frame_pointer: u64,
+    stack_pointer: u64,
+) -> fmt::Result {
+    writeln!(console, "Backtrace:")?;
+    print_frame(console, 0, instruction_pointer, instruction_pointer)?;
+
+    let Some(mut stack) = stack_bounds(stack_pointer) else {
+        return Ok(());
+    };
+
+    for depth in 1..MAX_BACKTRACE_DEPTH {
+        if !is_valid_frame_pointer(frame_pointer, &stack) {
+            break;
+        }
+
+        let frame = frame_pointer as *const u64;
+        let return_address = unsafe { frame.add(1).read() };
+
+        if return_address == 0 {
+            break;
+        }
+
+        // The call may be the last instruction of its function, so look up the byte before the
+        // return address to not attribute it to whatever function follows.
+        print_frame(console, depth, return_address, return_address - 1)?;
+
+        // Callers' frames are higher up, so the walk cannot go around in circles.
+        stack.start = frame_pointer + 16;
+        frame_pointer = unsafe { frame.read() };
+    }
+
+    Ok(())
+}
+
+fn print_frame(
+    console: &mut CrashConsole,
+    depth: usize,
+    address: u64,
+    lookup_address: u64,
+) -> fmt::Result {
+    write!(console, "  #{:<2} {:016x}", depth, address)?;
+
+    match symbols::symbolize(lookup_address) {
+        Some((name, offset)) => writeln!(
+            console,
+            " {}+{:#x}",
+            name,
+            offset + (address - lookup_address)
+        ),
+        None => writeln!(console),
+    }
+}
+
+/// Addresses the frames of the stack `stack_pointer` points into can have. Thread stacks have a
+/// region of their own. The others (boot, interrupt and AP stacks) are statics in the kernel
+/// image, where only the stack pointer bounds them.
+fn stack_bounds(stack_pointer: u64) -> Option<Range<u64>> {
+    if let Some(stack) = thread::stack_bounds(stack_pointer) {
+        return Some(stack_pointer.max(stack.start)..stack.end);
+    }
+
+    (stack_pointer >= KERNEL_VIRTUAL_BASE as u64).then_some(stack_pointer..u64::MAX)
+}
+
+/// The frame, its saved RBP and return address, has to be within the stack.
+fn is_valid_frame_pointer(frame_pointer: u64, stack: &Range<u64>) -> bool {
+    frame_pointer & 0x7 == 0
+        && frame_pointer >= stack.start
+        && frame_pointer
+            .checked_add(16)
+            .is_some_and(|end| end <= stack.end)
+}
+
+pub fn halt_forever() -> ! {
+    interrupts::disable();
+
+    loop {
+        unsafe {
+            asm!("hlt", options(nomem, nostack, preserves_flags));
+        }
+    }
+}
@@ -1,4 +1,6 @@
-use core::arch::asm;
+use core::{arch::asm, mem::size_of};
+
+use crate::arch::x86_64::percpu;
 
 #[repr(C, packed)]
 #[derive(Eq, PartialEq, Debug, Clone, Copy, Default)]
@@ -25,8 +27,7 @@ impl GdtEntry {
     #[inline(always)]
     const fn set_base(&mut self, base: u32) {
         self.0 |= (base as u64 & 0xFFFFFF) << 16;
-        self.0 |= (base as u64 & 0xFF0000) << 32;
-        self.0 |= (base as u64 & 0xFF000000) << 56;
+        self.0 |= ((base as u64 >> 24) & 0xFF) << 56;
     }
 
     #[inline(always)]
@@ -48,170 +49,187 @@ struct GDTR<'a> {
 
 #[inline(always)]
 pub const fn segment_selector(rpl: u8, index: u16) -> u16 {
-    return (rpl as u16) | index << 3;
+    (rpl as u16) | index << 3
 }
 
+// Access byte: present, DPL, descriptor type (code/data), executable, readable/writable.
+const KERNEL_CODE_ACCESS: u8 = 0b10011010;
+const KERNEL_DATA_ACCESS: u8 = 0b10010010;
+const USER_CODE_ACCESS: u8 = 0b11111010;
+const USER_DATA_ACCESS: u8 = 0b11110010;
+
+// Flags: granularity and long mode (L) for code segments. Base and limit are ignored in long mode.
+const CODE_FLAGS: u8 = 0b1010;
+const DATA_FLAGS: u8 = 0b1100;
+
+const TSS_INDEX: u16 = 5;
+
 #[repr(C, packed)]
 struct GDT {
-    entries: [GdtEntry; 6],
+    // The TSS descriptor takes two entries in long mode.
+    entries: [GdtEntry; 7],
 }
 
-static mut GDT: GDT = GDT {
+// User data comes before user code because SYSRET loads SS and CS from fixed offsets of a single
+// selector base (STAR[63:48] + 8 and + 16).
+const INITIAL_GDT: GDT = GDT {
     entries: [
         GdtEntry::new(0, 0, 0, 0),
-        GdtEntry::new(!0, 0, 0b10011010, 0b1100),
-        GdtEntry::new(!0, 0, 0b10010010, 0b1100),
-        GdtEntry::new(!0, 0, 0b11111010, 0b1100),
-        GdtEntry::new(!0, 0, 0b11110010, 0b1100),
-        GdtEntry::new(!0, 0, 0, 0), // TSS
+        GdtEntry::new(!0, 0, KERNEL_CODE_ACCESS, CODE_FLAGS),
+        GdtEntry::new(!0, 0, KERNEL_DATA_ACCESS, DATA_FLAGS),
+        GdtEntry::new(!0, 0, USER_DATA_ACCESS, DATA_FLAGS),
+        GdtEntry::new(!0, 0, USER_CODE_ACCESS, CODE_FLAGS),
+        GdtEntry::new(0, 0, 0, 0), // TSS (low)
+        GdtEntry::new(0, 0, 0, 0), // TSS (high)
     ],
 };
 
+percpu! {
+    // Every CPU has its own GDT, since the TSS descriptor is marked busy once loaded.
+    static GDT: GDT = INITIAL_GDT;
+}
+
 pub const KERNEL_CODE_SEGMENT_SELECTOR: u16 = segment_selector(0, 1);
 pub const KERNEL_DATA_SEGMENT_SELECTOR: u16 = segment_selector(0, 2);
-pub const USER_CODE_SEGMENT_SELECTOR: u16 = segment_selector(3, 3);
-pub const USER_DATA_SEGMENT_SELECTOR: u16 = segment_selector(3, 4);
-
-const fn tss_descriptor(base: u32, limit: u32) -> GdtEntry {
-    let access: u8 = 0b10001001;
+pub const USER_DATA_SEGMENT_SELECTOR: u16 = segment_selector(3, 3);
+pub const USER_CODE_SEGMENT_SELECTOR: u16 = segment_selector(3, 4);
+pub const TSS_SEGMENT_SELECTOR: u16 = segment_selector(0, TSS_INDEX);
+
+/// Long mode TSS descriptors are 16 bytes: a regular system descriptor followed by the upper
+/// 32 bits of the base address.
+const fn tss_descriptor(base: u64, limit: u32) -> [GdtEntry; 2] {
+    let access: u8 = 0b10001001; // Present, 64-bit TSS (available)
     let flags: u8 = 0b0000;
 
-    GdtEntry::new(limit, base, access, flags)
+    [
+        GdtEntry::new(limit, base as u32, access, flags),
+        GdtEntry(base >> 32),
+    ]
 }
 
 #[repr(C, packed)]
 pub struct Tss {
-    pub prev_tss: u16,
-    pub reserved0: u16,
-    pub esp0: u32,
-    pub ss0: u16,
-    pub reserved1: u16,
-    pub esp1: u32,
-    pub ss1: u16,
-    pub reserved2: u16,
-    pub esp2: u32,
-    pub ss2: u16,
+    pub reserved0: u32,
+    /// Stack pointers loaded when an interrupt or call gate raises the privilege level to ring 0-2.
+    pub rsp: [u64; 3],
+    pub reserved1: u64,
+    /// Interrupt stack table. IST1 is `ist[0]`.
+    pub ist: [u64; 7],
+    pub reserved2: u64,
     pub reserved3: u16,
-    pub cr3: u32,
-    pub eip: u32,
-    pub eflags: u32,
-    pub eax: u32,
-    pub ecx: u32,
-    pub edx: u32,
-    pub ebx: u32,
-    pub esp: u32,
-    pub ebp: u32,
-    pub esi: u32,
-    pub edi: u32,
-    pub es: u16,
-    pub reserved4: u16,
-    pub cs: u16,
-    pub reserved5: u16,
-    pub ss: u16,
-    pub reserved6: u16,
-    pub ds: u16,
-    pub reserved7: u16,
-    pub fs: u16,
-    pub reserved8: u16,
-    pub gs: u16,
-    pub reserved9: u16,
-    pub ldt: u16,
-    pub reserved10: u16,
-    pub t_flag: u16,
     pub io_map_base: u16,
 }
 
-static mut TSS: Tss = Tss {
-    prev_tss: 0,
+const INITIAL_TSS: Tss = Tss {
     reserved0: 0,
-    esp0: 0,
-    ss0: 0x8, // kernel data segment selector
+    rsp: [0; 3],
     reserved1: 0,
-    esp1: 0,
-    ss1: 0,
+    ist: [0; 7],
     reserved2: 0,
-    esp2: 0,
-    ss2: 0,
     reserved3: 0,
-    cr3: 0,
-    eip: 0,
-    eflags: 0,
-    eax: 0,
-    ecx: 0,
-    edx: 0,
-    ebx: 0,
-    esp: 0,
-    ebp: 0,
-    esi: 0,
-    edi: 0,
-    es: 0,
-    reserved4: 0,
-    cs: 0,
-    reserved5: 0,
-    ss: 0,
-    reserved6: 0,
-    ds: 0,
-    reserved7: 0,
-    fs: 0,
-    reserved8: 0,
-    gs: 0,
-    reserved9: 0,
-    ldt: 0,
-    reserved10: 0,
-    t_flag: 0,
-    io_map_base: 0xFFFF,
+    // No I/O permission bitmap: the base points past the end of the TSS.
+    io_map_base: size_of::<Tss>() as u16,
 };
 
-#[repr(C, packed)]
-struct Stack([u8; 4096]);
+percpu! {
+    pub static TSS: Tss = INITIAL_TSS;
+}
 
-static mut KERNEL_STACK: Stack = Stack([0; 4096]);
+/// IST slots (1-based, as encoded in IDT gates) of exceptions that must not run on the
+/// interrupted stack, which may be the very stack that overflowed.
+pub const DOUBLE_FAULT_IST_INDEX: u8 = 1;
+pub const NMI_IST_INDEX: u8 = 2;
+pub const MACHINE_CHECK_IST_INDEX: u8 = 3;
 
+pub const STACK_SIZE: usize = 4096 * 4;
+
+#[repr(C, align(16))]
+pub struct Stack([u8; STACK_SIZE]);
+
+impl Stack {
+    pub const fn new() -> Self {
+        Self([0; STACK_SIZE])
+    }
+
+    pub fn top(&self) -> u64 {
+        self.0.as_ptr() as u64 + STACK_SIZE as u64
+    }
+}
+
+impl Default for Stack {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+percpu! {
+    static KERNEL_STACK: Stack = Stack::new();
+    static DOUBLE_FAULT_STACK: Stack = Stack::new();
+    static NMI_STACK: Stack = Stack::new();
+    static MACHINE_CHECK_STACK: Stack = Stack::new();
+}
+
+/// Stack the calling CPU switches to on interrupts and system calls from user mode.
+pub fn set_kernel_stack(stack_top: u64) {
+    unsafe { (*TSS.as_mut_ptr()).rsp[0] = stack_top };
+
+    percpu::set_kernel_stack_top(stack_top);
+}
+
+/// Stack set with `set_kernel_stack` on the calling CPU.
+pub fn kernel_stack() -> u64 {
+    unsafe { (*TSS.as_mut_ptr()).rsp[0] }
+}
+
+/// Loads the GDT and TSS of the CPU with index `cpu_index` in the CPU registry (see smp.rs).
+/// GS is not set up yet, so the per-CPU values are looked up by index.
 #[inline(never)]
-pub fn install() {
+pub fn install(cpu_index: usize) {
     unsafe {
+        let tss = &mut *TSS.as_mut_ptr_for(cpu_index);
+        let gdt = &mut *GDT.as_mut_ptr_for(cpu_index);
+
+        tss.rsp[0] = KERNEL_STACK.for_cpu(cpu_index).top();
+        tss.ist[DOUBLE_FAULT_IST_INDEX as usize - 1] = DOUBLE_FAULT_STACK.for_cpu(cpu_index).top();
+        tss.ist[NMI_IST_INDEX as usize - 1] = NMI_STACK.for_cpu(cpu_index).top();
+        tss.ist[MACHINE_CHECK_IST_INDEX as usize - 1] =
+            MACHINE_CHECK_STACK.for_cpu(cpu_index).top();
+
+        let [tss_low, tss_high] =
+            tss_descriptor(tss as *const _ as u64, size_of::<Tss>() as u32 - 1);
+
+        gdt.entries[TSS_INDEX as usize] = tss_low;
+        gdt.entries[TSS_INDEX as usize + 1] = tss_high;
+
         let gdtr = GDTR {
-            limit: 49,
-            #[allow(static_mut_refs)]
-            base: &GDT,
+            limit: (size_of::<GDT>() - 1) as u16,
+            base: gdt,
         };
 
-        asm!("lgdt [{}]", in(reg) &gdtr);
+        asm!("lgdt [{}]", in(reg) &gdtr, options(readonly, nostack, preserves_flags));
 
         asm!(
-            "mov ax, {0:x}",
-            "mov ds, ax",
-            "mov es, ax",
-            "mov fs, ax",
-            "mov gs, ax",
-            "mov ss, ax",
+            "mov ds, {0:x}",
+            "mov es, {0:x}",
+            "mov fs, {0:x}",
+            "mov gs, {0:x}",
+            "mov ss, {0:x}",
             in(reg) KERNEL_DATA_SEGMENT_SELECTOR,
             options(nostack, preserves_flags)
         );
 
+        // CS can only be reloaded through a far control transfer.
         asm!(
-            "call 3f",
-            "jmp 4f",
-            "3:",
-            "pop {tmp}",
-            "push {selector:e}",
+            "push {selector}",
+            "lea {tmp}, [rip + 2f]",
             "push {tmp}",
-            "retf",
-            "4:",
-            selector = in(reg) KERNEL_CODE_SEGMENT_SELECTOR,
-            tmp = out(reg) _,
+            "retfq",
+            "2:",
+            selector = in(reg) KERNEL_CODE_SEGMENT_SELECTOR as u64,
+            tmp = lateout(reg) _,
             options(preserves_flags),
         );
 
-        TSS.esp0 = (&KERNEL_STACK.0[4095]) as *const _ as u32;
-        TSS.esp1 = (&KERNEL_STACK.0[4095]) as *const _ as u32;
-
-        #[allow(static_mut_refs)]
-        let tss = &TSS as *const _ as u32;
-
-        GDT.entries[5] = tss_descriptor(tss, core::mem::size_of::<Tss>() as u32 - 1);
-
-        let tss_segment_selector: u16 = 5 << 3;
-        asm!("ltr {0:x}", in(reg) tss_segment_selector);
+        asm!("ltr {0:x}", in(reg) TSS_SEGMENT_SELECTOR, options(nostack, preserves_flags));
     }
 }
@@ -0,0 +1,63 @@
+use core::ptr;
+
+use crate::memory::layout;
+
+const REGISTER_CAPABILITIES: usize = 0x00;
+const REGISTER_CONFIGURATION: usize = 0x10;
+const REGISTER_MAIN_COUNTER: usize = 0xF0;
+
+const CONFIGURATION_ENABLE: u64 = 1 << 0;
+const CAPABILITY_64_BIT_COUNTER: u64 = 1 << 13;
+
+const FEMTOSECONDS_PER_SECOND: u64 = 1_000_000_000_000_000;
+
+/// The HPET main counter, used as a clock source. Its comparators are left unused.
+#[derive(Debug, Clone, Copy)]
+pub struct Hpet {
+    base: usize,
+    /// Counter frequency in Hz.
+    pub frequency: u64,
+}
+
+impl Hpet {
+    /// Starts the main counter of the HPET at `physical_address`. Returns `None` for HPETs with
+    /// a 32-bit counter, which would wrap around every few minutes.
+    ///
+    /// # Safety
+    ///
+    /// `physical_address` must be the register block of an HPET.
+    pub unsafe fn init(physical_address: u64) -> Option<Self> {
+        let mut hpet = Self {
+            base: layout::physical_to_virtual(physical_address as usize),
+            frequency: 0,
+        };
+
+        let capabilities = hpet.read(REGISTER_CAPABILITIES);
+        // Bits 32-63 hold the counter period in femtoseconds.
+        let period = capabilities >> 32;
+
+        if capabilities & CAPABILITY_64_BIT_COUNTER == 0 || period == 0 {
+            return None;
+        }
+
+        hpet.frequency = FEMTOSECONDS_PER_SECOND / period;
+
+        let configuration = hpet.read(REGISTER_CONFIGURATION);
+
+        hpet.write(REGISTER_CONFIGURATION, configuration | CONFIGURATION_ENABLE);
+
+        Some(hpet)
+    }
+
+    pub fn counter(&self) -> u64 {
+        self.read(REGISTER_MAIN_COUNTER)
+    }
+
+    fn read(&self, register: usize) -> u64 {
+        unsafe { ptr::read_volatile((self.base + register) as *const u64) }
+    }
+
+    fn write(&self, register: usize, value: u64) {
+        unsafe { ptr::write_volatile((self.base + register) as *mut u64, value) }
+    }
+}
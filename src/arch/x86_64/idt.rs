@@ -1,18 +1,29 @@
-use core::arch::asm;
+use core::{
+    arch::asm,
+    fmt,
+    mem::size_of,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+};
 
-use crate::{
-    arch::x86_64::{interrupts, io},
-    println,
+use crate::arch::x86_64::{
+    control_registers::read_cr2,
+    crash,
+    gdt::{self, DOUBLE_FAULT_IST_INDEX, MACHINE_CHECK_IST_INDEX, NMI_IST_INDEX},
 };
 
+const INTERRUPT_GATE: u8 = 0x8E; // Present, DPL 0, 64-bit interrupt gate
+const USER_CALLABLE: u8 = 0x60; // DPL 3
+
 #[repr(C, packed)]
 #[derive(Copy, Clone)]
 struct IdtEntry {
     offset_low: u16,
     selector: u16,
-    zero: u8,
+    ist: u8,
     type_attr: u8,
-    offset_high: u16,
+    offset_middle: u16,
+    offset_high: u32,
+    reserved: u32,
 }
 
 impl IdtEntry {
@@ -20,17 +31,24 @@ impl IdtEntry {
         IdtEntry {
             offset_low: 0,
             selector: 0,
-            zero: 0,
+            ist: 0,
             type_attr: 0,
+            offset_middle: 0,
             offset_high: 0,
+            reserved: 0,
         }
     }
 
-    unsafe fn set_handler(&mut self, handler_virtual_addr: u64) {
+    fn set_handler(&mut self, handler_virtual_addr: u64) {
         self.offset_low = (handler_virtual_addr & 0xFFFF) as u16;
-        self.offset_high = ((handler_virtual_addr >> 16) & 0xFFFF) as u16;
-        self.selector = 0x8;
-        self.type_attr = 0x8E | 0x60;
+        self.offset_middle = ((handler_virtual_addr >> 16) & 0xFFFF) as u16;
+        self.offset_high = (handler_virtual_addr >> 32) as u32;
+        self.selector = gdt::KERNEL_CODE_SEGMENT_SELECTOR;
+        self.type_attr = INTERRUPT_GATE;
+    }
+
+    fn set_stack_index(&mut self, ist_index: u8) {
+        self.ist = ist_index & 0b111;
     }
 }
 
@@ -43,58 +61,247 @@ pub static mut IDT: Idt = Idt {
     entries: [IdtEntry::new(); 256],
 };
 
-#[derive(Debug)]
+/// Frame pushed by the CPU on interrupt entry.
+#[derive(Debug, Clone, Copy)]
 #[repr(C)]
 pub struct InterruptStackFrame {
-    pub instruction_pointer: u32,
-    pub code_segment_selector: u16,
-    pub eflags: u32,
-    pub stack_pointer: u32,
-    pub stack_segment_selector: u16,
+    pub instruction_pointer: u64,
+    pub code_segment_selector: u64,
+    pub rflags: u64,
+    pub stack_pointer: u64,
+    pub stack_segment_selector: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Registers {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
 }
 
-extern "x86-interrupt" fn divide_by_zero_handler(mut stack_frame: InterruptStackFrame) {
-    crate::screen::vga::VGA_SCREEN.lock().write_char('Z');
+/// Everything saved by the entry stubs in interrupt_stubs.S, lowest address first. Vectors
+/// without an error code get a zero.
+#[derive(Debug)]
+#[repr(C)]
+pub struct InterruptContext {
+    pub registers: Registers,
+    pub vector: u64,
+    pub error_code: u64,
+    pub frame: InterruptStackFrame,
+}
+
+pub type InterruptHandler = fn(&mut InterruptContext);
+
+pub const EXCEPTION_COUNT: usize = 32;
+
+pub const BREAKPOINT_VECTOR: u8 = 3;
+pub const PAGE_FAULT_VECTOR: u8 = 14;
+
+pub const EXCEPTION_NAMES: [&str; EXCEPTION_COUNT] = [
+    "Divide Error",
+    "Debug",
+    "Non-Maskable Interrupt",
+    "Breakpoint",
+    "Overflow",
+    "BOUND Range Exceeded",
+    "Invalid Opcode",
+    "Device Not Available",
+    "Double Fault",
+    "Coprocessor Segment Overrun",
+    "Invalid TSS",
+    "Segment Not Present",
+    "Stack-Segment Fault",
+    "General Protection Fault",
+    "Page Fault",
+    "Reserved",
+    "x87 Floating-Point Exception",
+    "Alignment Check",
+    "Machine Check",
+    "SIMD Floating-Point Exception",
+    "Virtualization Exception",
+    "Control Protection Exception",
+    "Reserved",
+    "Reserved",
+    "Reserved",
+    "Reserved",
+    "Reserved",
+    "Reserved",
+    "Hypervisor Injection Exception",
+    "VMM Communication Exception",
+    "Security Exception",
+    "Reserved",
+];
+
+/// Error code of #TS, #NP, #SS and #GP, describing the segment selector that caused the fault.
+pub struct SelectorErrorCode(pub u64);
 
-    // FIXME: Just to test. Should panic instead.
-    stack_frame.instruction_pointer += 2;
+impl fmt::Display for SelectorErrorCode {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0 == 0 {
+            return formatter.write_str("not segment related");
+        }
+
+        let table = match (self.0 >> 1) & 0b11 {
+            0b00 => "GDT",
+            0b10 => "LDT",
+            _ => "IDT",
+        };
+
+        write!(formatter, "{} index {}", table, (self.0 >> 3) & 0x1FFF)?;
+
+        if self.0 & 1 != 0 {
+            formatter.write_str(", external")?;
+        }
 
-    loop {}
+        Ok(())
+    }
 }
 
-extern "C" fn double_fault_handler() {
-    crate::screen::vga::VGA_SCREEN
-        .lock()
-        .write_string("Double fault");
+pub struct PageFaultErrorCode(pub u64);
+
+impl fmt::Display for PageFaultErrorCode {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str(if self.0 & (1 << 0) != 0 {
+            "protection violation"
+        } else {
+            "page not present"
+        })?;
+        formatter.write_str(if self.0 & (1 << 1) != 0 {
+            ", write"
+        } else {
+            ", read"
+        })?;
+        formatter.write_str(if self.0 & (1 << 2) != 0 {
+            ", user mode"
+        } else {
+            ", kernel mode"
+        })?;
+
+        const FLAGS: [(u32, &str); 5] = [
+            (3, ", reserved bit set"),
+            (4, ", instruction fetch"),
+            (5, ", protection key"),
+            (6, ", shadow stack"),
+            (15, ", SGX"),
+        ];
 
-    loop {}
+        for (bit, description) in FLAGS {
+            if self.0 & (1 << bit) != 0 {
+                formatter.write_str(description)?;
+            }
+        }
+
+        Ok(())
+    }
 }
 
-#[no_mangle]
-fn general_protection_fault_handler(error_code: u32) {
-    println!("GENERAL PROTECTION FAULT {:#?}", error_code);
+/// Human readable description of an exception error code, or `None` for exceptions without one.
+pub struct ExceptionErrorCode {
+    vector: u8,
+    error_code: u64,
+}
+
+impl ExceptionErrorCode {
+    pub fn new(vector: u8, error_code: u64) -> Option<Self> {
+        match vector {
+            8 | 10..=14 | 17 | 21 | 29 | 30 => Some(Self { vector, error_code }),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ExceptionErrorCode {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(formatter, "{:#x} (", self.error_code)?;
+
+        match self.vector {
+            10..=13 => SelectorErrorCode(self.error_code).fmt(formatter)?,
+            PAGE_FAULT_VECTOR => {
+                PageFaultErrorCode(self.error_code).fmt(formatter)?;
+                write!(formatter, ", address {:#x}", read_cr2())?;
+            }
+            _ => formatter.write_str("no description")?,
+        }
+
+        formatter.write_str(")")
+    }
+}
+
+static HANDLERS: [AtomicUsize; 256] = [const { AtomicUsize::new(0) }; 256];
 
-    loop {}
+percpu! {
+    static INTERRUPT_COUNT: AtomicU64 = AtomicU64::new(0);
+}
+
+/// Interrupts and exceptions taken by the CPU with `cpu_index` since boot.
+pub fn interrupt_count(cpu_index: usize) -> u64 {
+    INTERRUPT_COUNT.for_cpu(cpu_index).load(Ordering::Relaxed)
+}
+
+/// Routes `vector` to `handler`. Exceptions without a handler are fatal, other vectors are ignored.
+pub fn set_handler(vector: u8, handler: InterruptHandler) {
+    HANDLERS[vector as usize].store(handler as usize, Ordering::Release);
 }
 
 #[no_mangle]
-fn general_protection_fault_handler_stub() {
-    unsafe {
-        asm!(
-            "add esp, 24",
-            "push eax",
-            "push 0",
-            "jmp general_protection_fault_handler"
-        );
+extern "C" fn interrupt_dispatch(context: &mut InterruptContext) {
+    INTERRUPT_COUNT.get().fetch_add(1, Ordering::Relaxed);
+
+    let handler = HANDLERS[context.vector as usize].load(Ordering::Acquire);
+
+    if handler != 0 {
+        let handler: InterruptHandler = unsafe { core::mem::transmute(handler) };
+
+        handler(context);
+    } else if (context.vector as usize) < EXCEPTION_COUNT {
+        crash::exception(context);
     }
 }
 
+fn breakpoint_handler(context: &mut InterruptContext) {
+    println!("Breakpoint at {:#x}", context.frame.instruction_pointer - 1);
+}
+
+extern "C" {
+    static interrupt_stub_table: [u64; 256];
+}
+
+#[allow(static_mut_refs)]
 pub fn init_idt() {
     unsafe {
-        IDT.entries[0].set_handler(divide_by_zero_handler as u64);
-        IDT.entries[8].set_handler(double_fault_handler as u64);
-        IDT.entries[13].set_handler(general_protection_fault_handler_stub as u64);
+        for (vector, entry) in IDT.entries.iter_mut().enumerate() {
+            entry.set_handler(interrupt_stub_table[vector]);
+        }
+
+        IDT.entries[2].set_stack_index(NMI_IST_INDEX);
+        IDT.entries[8].set_stack_index(DOUBLE_FAULT_IST_INDEX);
+        IDT.entries[18].set_stack_index(MACHINE_CHECK_IST_INDEX);
+
+        // Allow `int3` from user mode.
+        IDT.entries[BREAKPOINT_VECTOR as usize].type_attr |= USER_CALLABLE;
+
+        set_handler(BREAKPOINT_VECTOR, breakpoint_handler);
+    }
+
+    load();
+}
 
+/// Loads the IDT on the calling CPU. All CPUs share it.
+pub fn load() {
+    unsafe {
         lidt(&raw const IDT);
     }
 }
@@ -102,21 +309,14 @@ pub fn init_idt() {
 #[repr(C, packed)]
 struct Idtr {
     limit: u16,
-    base: u32,
+    base: u64,
 }
 
 unsafe fn lidt(idt: *const Idt) {
     let idtr = Idtr {
-        limit: (256 * 8) - 1,
-        base: idt as *const _ as u32,
+        limit: (size_of::<Idt>() - 1) as u16,
+        base: idt as u64,
     };
 
-    asm!("lidt [{}]", in(reg) &idtr);
-
-    unsafe {
-        io::outportb(0x21, 0xFF);
-        io::outportb(0xA1, 0xFF);
-    }
-
-    interrupts::enable();
+    asm!("lidt [{}]", in(reg) &idtr, options(readonly, nostack, preserves_flags));
 }
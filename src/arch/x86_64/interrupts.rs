@@ -1,18 +1,23 @@
 use core::arch::asm;
 
 #[inline]
-pub fn is_enabled() -> bool {
-    let mut eflags: u32;
+pub fn read_rflags() -> u64 {
+    let mut rflags: u64;
 
     unsafe {
         asm!(
-          "pushfd",
-          "pop {eflags:e}",
-          eflags = out(reg) eflags
+          "pushfq",
+          "pop {rflags}",
+          rflags = out(reg) rflags
         );
     }
 
-    (eflags & (1 << 9)) != 0
+    rflags
+}
+
+#[inline]
+pub fn is_enabled() -> bool {
+    (read_rflags() & (1 << 9)) != 0
 }
 
 #[inline]
@@ -29,12 +34,21 @@ pub fn disable() {
     }
 }
 
+/// Waits for the next interrupt.
+#[inline]
+pub fn halt() {
+    unsafe {
+        asm!("hlt", options(nomem, nostack, preserves_flags));
+    }
+}
+
+/// Runs `f` with interrupts disabled, then restores the previous state, so calls can nest.
 #[inline]
 pub fn without_interrupts<F, R>(f: F) -> R
 where
     F: FnOnce() -> R,
 {
-    let enabled = false;
+    let enabled = is_enabled();
 
     if enabled {
         disable();
@@ -43,7 +57,7 @@ where
     let result = f();
 
     if enabled {
-        disable();
+        enable();
     }
 
     result
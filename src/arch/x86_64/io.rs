@@ -27,3 +27,14 @@ pub unsafe fn inportl(port: u16) -> u32 {
 
     return data;
 }
+
+/// Gives slow devices such as the 8259 PIC time to settle between two port accesses by
+/// writing to the unused POST diagnostic port.
+///
+/// # Safety
+///
+/// Port 0x80 must not be in use by any device on the machine.
+#[inline(always)]
+pub unsafe fn io_wait() {
+    outportb(0x80, 0);
+}
@@ -0,0 +1,205 @@
+use core::ptr;
+
+use spin::Mutex;
+
+use crate::memory::layout;
+
+// The I/O APIC exposes its registers indirectly: the index is written to IOREGSEL, then the value
+// is accessed through IOWIN.
+const IOREGSEL: usize = 0x00;
+const IOWIN: usize = 0x10;
+
+const REGISTER_VERSION: u32 = 0x01;
+const REGISTER_REDIRECTION_TABLE: u32 = 0x10;
+
+const MAX_IO_APICS: usize = 8;
+
+const REDIRECTION_ACTIVE_LOW: u64 = 1 << 13;
+const REDIRECTION_LEVEL_TRIGGERED: u64 = 1 << 15;
+const REDIRECTION_MASKED: u64 = 1 << 16;
+
+/// An I/O APIC as described by the MADT.
+#[derive(Debug, Clone, Copy)]
+pub struct IoApicInfo {
+    pub id: u8,
+    pub address: u32,
+    /// First global system interrupt handled by this I/O APIC.
+    pub gsi_base: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Polarity {
+    ActiveHigh,
+    ActiveLow,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriggerMode {
+    Edge,
+    Level,
+}
+
+/// Decodes the MPS INTI flags of MADT entries. "Conforms to the bus" resolves to the ISA defaults,
+/// edge triggered and active high, since overrides only exist for ISA interrupts.
+pub fn decode_mps_inti_flags(flags: u16) -> (Polarity, TriggerMode) {
+    let polarity = match flags & 0b11 {
+        0b11 => Polarity::ActiveLow,
+        _ => Polarity::ActiveHigh,
+    };
+    let trigger_mode = match (flags >> 2) & 0b11 {
+        0b11 => TriggerMode::Level,
+        _ => TriggerMode::Edge,
+    };
+
+    (polarity, trigger_mode)
+}
+
+/// ISA interrupt wired to a different global system interrupt, or with non-default polarity or
+/// trigger mode.
+#[derive(Debug, Clone, Copy)]
+pub struct InterruptSourceOverride {
+    pub source: u8,
+    pub gsi: u32,
+    pub polarity: Polarity,
+    pub trigger_mode: TriggerMode,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RedirectionEntry {
+    pub vector: u8,
+    /// APIC ID of the CPU the interrupt is delivered to.
+    pub destination: u8,
+    pub polarity: Polarity,
+    pub trigger_mode: TriggerMode,
+    pub masked: bool,
+}
+
+impl RedirectionEntry {
+    fn encode(&self) -> u64 {
+        let mut value = self.vector as u64 | (self.destination as u64) << 56;
+
+        if self.polarity == Polarity::ActiveLow {
+            value |= REDIRECTION_ACTIVE_LOW;
+        }
+
+        if self.trigger_mode == TriggerMode::Level {
+            value |= REDIRECTION_LEVEL_TRIGGERED;
+        }
+
+        if self.masked {
+            value |= REDIRECTION_MASKED;
+        }
+
+        value
+    }
+}
+
+#[derive(Debug)]
+pub enum IoApicError {
+    /// No I/O APIC handles this global system interrupt.
+    InvalidGsi(u32),
+    TooManyIoApics,
+}
+
+#[derive(Clone, Copy)]
+struct IoApic {
+    base: usize,
+    gsi_base: u32,
+    redirection_count: u32,
+}
+
+impl IoApic {
+    fn read(&self, register: u32) -> u32 {
+        unsafe {
+            ptr::write_volatile((self.base + IOREGSEL) as *mut u32, register);
+            ptr::read_volatile((self.base + IOWIN) as *const u32)
+        }
+    }
+
+    fn write(&self, register: u32, value: u32) {
+        unsafe {
+            ptr::write_volatile((self.base + IOREGSEL) as *mut u32, register);
+            ptr::write_volatile((self.base + IOWIN) as *mut u32, value);
+        }
+    }
+
+    fn handles(&self, gsi: u32) -> bool {
+        gsi >= self.gsi_base && gsi < self.gsi_base + self.redirection_count
+    }
+
+    fn read_redirection(&self, index: u32) -> u64 {
+        let register = REGISTER_REDIRECTION_TABLE + index * 2;
+
+        self.read(register) as u64 | (self.read(register + 1) as u64) << 32
+    }
+
+    fn write_redirection(&self, index: u32, value: u64) {
+        let register = REGISTER_REDIRECTION_TABLE + index * 2;
+
+        // Keep the entry masked while its halves disagree.
+        self.write(register, REDIRECTION_MASKED as u32);
+        self.write(register + 1, (value >> 32) as u32);
+        self.write(register, value as u32);
+    }
+}
+
+static IO_APICS: Mutex<[Option<IoApic>; MAX_IO_APICS]> = Mutex::new([None; MAX_IO_APICS]);
+
+/// Registers the I/O APICs and masks all of their inputs.
+pub fn init(io_apics: impl IntoIterator<Item = IoApicInfo>) -> Result<(), IoApicError> {
+    let mut slots = IO_APICS.lock();
+    let mut slots = slots.iter_mut();
+
+    for info in io_apics {
+        let slot = slots.next().ok_or(IoApicError::TooManyIoApics)?;
+        let mut io_apic = IoApic {
+            base: layout::physical_to_virtual(info.address as usize),
+            gsi_base: info.gsi_base,
+            redirection_count: 0,
+        };
+
+        // Bits 16-23 hold the index of the last redirection entry.
+        io_apic.redirection_count = ((io_apic.read(REGISTER_VERSION) >> 16) & 0xFF) + 1;
+
+        for index in 0..io_apic.redirection_count {
+            io_apic.write_redirection(index, REDIRECTION_MASKED);
+        }
+
+        *slot = Some(io_apic);
+    }
+
+    Ok(())
+}
+
+fn with_io_apic<R>(gsi: u32, f: impl FnOnce(&IoApic, u32) -> R) -> Result<R, IoApicError> {
+    let slots = IO_APICS.lock();
+
+    slots
+        .iter()
+        .flatten()
+        .find(|io_apic| io_apic.handles(gsi))
+        .map(|io_apic| f(io_apic, gsi - io_apic.gsi_base))
+        .ok_or(IoApicError::InvalidGsi(gsi))
+}
+
+pub fn set_redirection(gsi: u32, entry: RedirectionEntry) -> Result<(), IoApicError> {
+    with_io_apic(gsi, |io_apic, index| {
+        io_apic.write_redirection(index, entry.encode())
+    })
+}
+
+pub fn mask(gsi: u32) -> Result<(), IoApicError> {
+    with_io_apic(gsi, |io_apic, index| {
+        let value = io_apic.read_redirection(index);
+
+        io_apic.write_redirection(index, value | REDIRECTION_MASKED);
+    })
+}
+
+pub fn unmask(gsi: u32) -> Result<(), IoApicError> {
+    with_io_apic(gsi, |io_apic, index| {
+        let value = io_apic.read_redirection(index);
+
+        io_apic.write_redirection(index, value & !REDIRECTION_MASKED);
+    })
+}
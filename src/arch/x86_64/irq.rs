@@ -0,0 +1,247 @@
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+
+use crate::arch::x86_64::{
+    idt::{self, InterruptContext},
+    interrupts,
+    io_apic::{
+        self, InterruptSourceOverride, IoApicError, IoApicInfo, Polarity, RedirectionEntry,
+        TriggerMode,
+    },
+    local_apic, pic,
+};
+
+pub const LINE_COUNT: u8 = pic::LINE_COUNT;
+
+/// Devices sharing a line (common with PCI INTx) are chained, each gets a chance to claim the interrupt.
+const MAX_HANDLERS_PER_LINE: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrqResult {
+    Handled,
+    /// The interrupt did not come from this handler's device.
+    NotHandled,
+}
+
+#[derive(Debug)]
+pub enum IrqError {
+    InvalidLine(u8),
+    TooManyHandlers(u8),
+}
+
+pub type IrqHandler = fn(&mut InterruptContext) -> IrqResult;
+
+static HANDLERS: [[AtomicUsize; MAX_HANDLERS_PER_LINE]; LINE_COUNT as usize] =
+    [const { [const { AtomicUsize::new(0) }; MAX_HANDLERS_PER_LINE] }; LINE_COUNT as usize];
+
+static COUNTS: [AtomicU64; LINE_COUNT as usize] =
+    [const { AtomicU64::new(0) }; LINE_COUNT as usize];
+static UNHANDLED_COUNTS: [AtomicU64; LINE_COUNT as usize] =
+    [const { AtomicU64::new(0) }; LINE_COUNT as usize];
+static SPURIOUS_COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// Set once the lines are routed through the I/O APIC instead of the 8259 PICs.
+static USING_APIC: AtomicBool = AtomicBool::new(false);
+
+/// Global system interrupt each ISA line is wired to, once routed through the I/O APIC.
+static LINE_GSIS: [AtomicU32; LINE_COUNT as usize] =
+    [const { AtomicU32::new(0) }; LINE_COUNT as usize];
+
+/// Lines that cannot be routed because another line took over their GSI.
+const UNROUTED_GSI: u32 = u32::MAX;
+
+pub fn init() {
+    pic::init();
+
+    for line in 0..LINE_COUNT {
+        idt::set_handler(vector(line), dispatch);
+    }
+}
+
+pub const fn vector(line: u8) -> u8 {
+    pic::MASTER_VECTOR_OFFSET + line
+}
+
+pub fn is_using_apic() -> bool {
+    USING_APIC.load(Ordering::Acquire)
+}
+
+/// Moves the ISA lines from the 8259 PICs to the local APIC and I/O APICs, applying the interrupt
+/// source overrides from the MADT. Vectors stay the same, so registered handlers keep working.
+pub fn use_apic(
+    io_apics: impl IntoIterator<Item = IoApicInfo>,
+    overrides: impl Iterator<Item = InterruptSourceOverride> + Clone,
+) -> Result<(), IoApicError> {
+    interrupts::without_interrupts(|| route_through_apic(io_apics, overrides))
+}
+
+fn route_through_apic(
+    io_apics: impl IntoIterator<Item = IoApicInfo>,
+    overrides: impl Iterator<Item = InterruptSourceOverride> + Clone,
+) -> Result<(), IoApicError> {
+    local_apic::init();
+    io_apic::init(io_apics)?;
+
+    idt::set_handler(local_apic::SPURIOUS_VECTOR, spurious_apic_interrupt);
+
+    let destination = local_apic::id() as u8;
+
+    for line in 0..LINE_COUNT {
+        let (gsi, polarity, trigger_mode) = match overrides.clone().find(|o| o.source == line) {
+            Some(o) => (o.gsi, o.polarity, o.trigger_mode),
+            None => (line as u32, Polarity::ActiveHigh, TriggerMode::Edge),
+        };
+
+        // Typically the timer moves to GSI 2, which the PIC cascade would otherwise own.
+        if overrides.clone().any(|o| o.gsi == gsi && o.source != line) {
+            LINE_GSIS[line as usize].store(UNROUTED_GSI, Ordering::Release);
+
+            continue;
+        }
+
+        let entry = RedirectionEntry {
+            vector: vector(line),
+            destination,
+            polarity,
+            trigger_mode,
+            masked: !has_handlers(line),
+        };
+
+        io_apic::set_redirection(gsi, entry)?;
+
+        LINE_GSIS[line as usize].store(gsi, Ordering::Release);
+    }
+
+    pic::disable();
+
+    USING_APIC.store(true, Ordering::Release);
+
+    Ok(())
+}
+
+/// Adds `handler` to the chain of `line` and unmasks it.
+pub fn register(line: u8, handler: IrqHandler) -> Result<(), IrqError> {
+    let slots = HANDLERS
+        .get(line as usize)
+        .ok_or(IrqError::InvalidLine(line))?;
+
+    let registered = slots.iter().any(|slot| {
+        slot.compare_exchange(0, handler as usize, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+    });
+
+    if !registered {
+        return Err(IrqError::TooManyHandlers(line));
+    }
+
+    unmask(line);
+
+    Ok(())
+}
+
+/// Removes `handler` from the chain of `line`, masking the line once nobody listens to it.
+pub fn unregister(line: u8, handler: IrqHandler) {
+    let Some(slots) = HANDLERS.get(line as usize) else {
+        return;
+    };
+
+    for slot in slots {
+        let _ = slot.compare_exchange(handler as usize, 0, Ordering::AcqRel, Ordering::Acquire);
+    }
+
+    if slots.iter().all(|slot| slot.load(Ordering::Acquire) == 0) {
+        mask(line);
+    }
+}
+
+pub fn mask(line: u8) {
+    if !is_using_apic() {
+        return pic::mask(line);
+    }
+
+    let gsi = LINE_GSIS[line as usize].load(Ordering::Acquire);
+
+    if gsi != UNROUTED_GSI {
+        let _ = io_apic::mask(gsi);
+    }
+}
+
+pub fn unmask(line: u8) {
+    if !is_using_apic() {
+        return pic::unmask(line);
+    }
+
+    let gsi = LINE_GSIS[line as usize].load(Ordering::Acquire);
+
+    if gsi != UNROUTED_GSI {
+        let _ = io_apic::unmask(gsi);
+    }
+}
+
+fn has_handlers(line: u8) -> bool {
+    HANDLERS[line as usize]
+        .iter()
+        .any(|slot| slot.load(Ordering::Acquire) != 0)
+}
+
+fn end_of_interrupt(line: u8) {
+    if is_using_apic() {
+        local_apic::end_of_interrupt();
+    } else {
+        pic::end_of_interrupt(line);
+    }
+}
+
+/// Interrupts received on `line` since boot.
+pub fn count(line: u8) -> u64 {
+    COUNTS[line as usize].load(Ordering::Relaxed)
+}
+
+/// Interrupts on `line` that no handler claimed.
+pub fn unhandled_count(line: u8) -> u64 {
+    UNHANDLED_COUNTS[line as usize].load(Ordering::Relaxed)
+}
+
+pub fn spurious_count() -> u64 {
+    SPURIOUS_COUNT.load(Ordering::Relaxed)
+}
+
+fn dispatch(context: &mut InterruptContext) {
+    let line = context.vector as u8 - pic::MASTER_VECTOR_OFFSET;
+
+    if !is_using_apic() && pic::is_spurious(line) {
+        SPURIOUS_COUNT.fetch_add(1, Ordering::Relaxed);
+        pic::acknowledge_spurious(line);
+
+        return;
+    }
+
+    COUNTS[line as usize].fetch_add(1, Ordering::Relaxed);
+
+    // Interrupt gates keep IF cleared until `iretq`, so acknowledging first cannot nest this
+    // handler. It also lets a handler switch to another thread without leaving the line blocked.
+    end_of_interrupt(line);
+
+    let mut result = IrqResult::NotHandled;
+
+    for slot in &HANDLERS[line as usize] {
+        let handler = slot.load(Ordering::Acquire);
+
+        if handler == 0 {
+            continue;
+        }
+
+        let handler: IrqHandler = unsafe { core::mem::transmute(handler) };
+
+        if handler(context) == IrqResult::Handled {
+            result = IrqResult::Handled;
+        }
+    }
+
+    if result == IrqResult::NotHandled {
+        UNHANDLED_COUNTS[line as usize].fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+fn spurious_apic_interrupt(_context: &mut InterruptContext) {
+    SPURIOUS_COUNT.fetch_add(1, Ordering::Relaxed);
+}
@@ -0,0 +1,185 @@
+use core::{
+    ptr,
+    sync::atomic::{AtomicU8, AtomicUsize, Ordering},
+};
+
+use crate::{
+    arch::x86_64::{
+        cpu::{self, Feature},
+        msr::{read_msr, write_msr, IA32_APIC_BASE},
+    },
+    memory::layout,
+};
+
+// Register offsets in the xAPIC MMIO page. x2APIC exposes the same registers as MSRs starting at
+// 0x800, one per 16 bytes of the MMIO layout.
+pub const ID: u32 = 0x020;
+pub const VERSION: u32 = 0x030;
+pub const TASK_PRIORITY: u32 = 0x080;
+pub const END_OF_INTERRUPT: u32 = 0x0B0;
+pub const SPURIOUS_INTERRUPT_VECTOR: u32 = 0x0F0;
+pub const ERROR_STATUS: u32 = 0x280;
+pub const INTERRUPT_COMMAND_LOW: u32 = 0x300;
+pub const INTERRUPT_COMMAND_HIGH: u32 = 0x310;
+pub const LVT_TIMER: u32 = 0x320;
+pub const LVT_LINT0: u32 = 0x350;
+pub const LVT_LINT1: u32 = 0x360;
+pub const LVT_ERROR: u32 = 0x370;
+pub const TIMER_INITIAL_COUNT: u32 = 0x380;
+pub const TIMER_CURRENT_COUNT: u32 = 0x390;
+pub const TIMER_DIVIDE_CONFIGURATION: u32 = 0x3E0;
+
+const X2APIC_MSR_BASE: u32 = 0x800;
+
+const APIC_BASE_X2APIC_ENABLE: u64 = 1 << 10;
+const APIC_BASE_GLOBAL_ENABLE: u64 = 1 << 11;
+const APIC_BASE_ADDRESS_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+
+const SOFTWARE_ENABLE: u32 = 1 << 8;
+
+// Interrupt command register fields.
+const DELIVERY_MODE_FIXED: u32 = 0b000 << 8;
+const DELIVERY_MODE_INIT: u32 = 0b101 << 8;
+const DELIVERY_MODE_STARTUP: u32 = 0b110 << 8;
+const DELIVERY_STATUS_PENDING: u32 = 1 << 12;
+const LEVEL_ASSERT: u32 = 1 << 14;
+
+/// Delivered when an interrupt is withdrawn between being signaled and accepted. It must not be
+/// acknowledged with an EOI.
+pub const SPURIOUS_VECTOR: u8 = 0xFF;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Mode {
+    Disabled = 0,
+    XApic = 1,
+    X2Apic = 2,
+}
+
+static MODE: AtomicU8 = AtomicU8::new(Mode::Disabled as u8);
+static BASE: AtomicUsize = AtomicUsize::new(0);
+
+pub fn is_supported() -> bool {
+    cpu::has(Feature::Apic)
+}
+
+pub fn supports_x2apic() -> bool {
+    cpu::has(Feature::X2Apic)
+}
+
+pub fn mode() -> Mode {
+    match MODE.load(Ordering::Acquire) {
+        1 => Mode::XApic,
+        2 => Mode::X2Apic,
+        _ => Mode::Disabled,
+    }
+}
+
+/// Enables the local APIC of the calling CPU, in x2APIC mode when available.
+pub fn init() {
+    let x2apic = supports_x2apic();
+
+    unsafe {
+        let mut apic_base = read_msr(IA32_APIC_BASE) | APIC_BASE_GLOBAL_ENABLE;
+
+        if x2apic {
+            apic_base |= APIC_BASE_X2APIC_ENABLE;
+        }
+
+        write_msr(IA32_APIC_BASE, apic_base);
+
+        BASE.store(
+            layout::physical_to_virtual((apic_base & APIC_BASE_ADDRESS_MASK) as usize),
+            Ordering::Release,
+        );
+    }
+
+    let mode = if x2apic { Mode::X2Apic } else { Mode::XApic };
+
+    MODE.store(mode as u8, Ordering::Release);
+
+    write(
+        SPURIOUS_INTERRUPT_VECTOR,
+        SOFTWARE_ENABLE | SPURIOUS_VECTOR as u32,
+    );
+    // Accept interrupts of every priority class.
+    write(TASK_PRIORITY, 0);
+}
+
+pub fn read(register: u32) -> u32 {
+    match mode() {
+        Mode::X2Apic => unsafe { read_msr(X2APIC_MSR_BASE + (register >> 4)) as u32 },
+        Mode::XApic => unsafe {
+            ptr::read_volatile((BASE.load(Ordering::Acquire) + register as usize) as *const u32)
+        },
+        Mode::Disabled => 0,
+    }
+}
+
+pub fn write(register: u32, value: u32) {
+    match mode() {
+        Mode::X2Apic => unsafe { write_msr(X2APIC_MSR_BASE + (register >> 4), value as u64) },
+        Mode::XApic => unsafe {
+            ptr::write_volatile(
+                (BASE.load(Ordering::Acquire) + register as usize) as *mut u32,
+                value,
+            )
+        },
+        Mode::Disabled => {}
+    }
+}
+
+/// APIC ID of the calling CPU.
+pub fn id() -> u32 {
+    match mode() {
+        Mode::X2Apic => read(ID),
+        _ => read(ID) >> 24,
+    }
+}
+
+pub fn end_of_interrupt() {
+    write(END_OF_INTERRUPT, 0);
+}
+
+/// Sends an inter-processor interrupt to the CPU with `apic_id`. `command` holds the low half of
+/// the interrupt command register.
+pub fn send_ipi(apic_id: u32, command: u32) {
+    match mode() {
+        // x2APIC has a single 64-bit register, writing it sends the IPI.
+        Mode::X2Apic => unsafe {
+            write_msr(
+                X2APIC_MSR_BASE + (INTERRUPT_COMMAND_LOW >> 4),
+                (apic_id as u64) << 32 | command as u64,
+            )
+        },
+        Mode::XApic => {
+            write(INTERRUPT_COMMAND_HIGH, apic_id << 24);
+            write(INTERRUPT_COMMAND_LOW, command);
+
+            while read(INTERRUPT_COMMAND_LOW) & DELIVERY_STATUS_PENDING != 0 {
+                core::hint::spin_loop();
+            }
+        }
+        Mode::Disabled => {}
+    }
+}
+
+pub fn send_fixed_ipi(apic_id: u32, vector: u8) {
+    send_ipi(apic_id, DELIVERY_MODE_FIXED | LEVEL_ASSERT | vector as u32);
+}
+
+/// Resets the CPU, which then waits for a startup IPI.
+pub fn send_init_ipi(apic_id: u32) {
+    send_ipi(apic_id, DELIVERY_MODE_INIT | LEVEL_ASSERT);
+}
+
+/// Starts a CPU waiting after an INIT IPI in real mode at physical address `page << 12`.
+pub fn send_startup_ipi(apic_id: u32, page: u8) {
+    send_ipi(apic_id, DELIVERY_MODE_STARTUP | LEVEL_ASSERT | page as u32);
+}
+
+/// Address and data to program into a device's MSI capability so it raises `vector` on the CPU
+/// with `apic_id` (fixed delivery, edge triggered).
+pub fn msi_message(vector: u8, apic_id: u8) -> (u64, u32) {
+    (0xFEE0_0000 | (apic_id as u64) << 12, vector as u32)
+}
@@ -1,7 +1,25 @@
+#[macro_use]
+pub mod percpu;
+
+pub mod context;
+pub mod control_registers;
+pub mod cpu;
+pub mod crash;
 pub mod gdt;
+pub mod hpet;
 pub mod idt;
 pub mod interrupts;
 pub mod io;
+pub mod io_apic;
+pub mod irq;
+pub mod local_apic;
+pub mod msr;
 pub mod paging;
+pub mod pic;
+pub mod pit;
 pub mod ring3;
+pub mod rtc;
 pub mod serial;
+pub mod smp;
+pub mod syscall;
+pub mod tsc;
@@ -0,0 +1,42 @@
+use core::arch::asm;
+
+pub const IA32_APIC_BASE: u32 = 0x1B;
+/// Memory types selected by the PAT, PCD and PWT bits of page table entries.
+pub const IA32_PAT: u32 = 0x277;
+pub const IA32_EFER: u32 = 0xC000_0080;
+/// Segment selector bases loaded by SYSCALL and SYSRET.
+pub const IA32_STAR: u32 = 0xC000_0081;
+/// 64-bit SYSCALL entry point.
+pub const IA32_LSTAR: u32 = 0xC000_0082;
+/// RFLAGS bits cleared by SYSCALL.
+pub const IA32_FMASK: u32 = 0xC000_0084;
+pub const IA32_GS_BASE: u32 = 0xC000_0101;
+/// Swapped with the GS base by `swapgs`.
+pub const IA32_KERNEL_GS_BASE: u32 = 0xC000_0102;
+
+/// # Safety
+///
+/// Reading an MSR the CPU does not implement raises #GP.
+#[inline]
+pub unsafe fn read_msr(msr: u32) -> u64 {
+    let low: u32;
+    let high: u32;
+
+    asm!("rdmsr", in("ecx") msr, out("eax") low, out("edx") high, options(nomem, nostack, preserves_flags));
+
+    (high as u64) << 32 | low as u64
+}
+
+/// # Safety
+///
+/// MSRs control core CPU behavior, and writing an unimplemented one raises #GP.
+#[inline]
+pub unsafe fn write_msr(msr: u32, value: u64) {
+    asm!(
+        "wrmsr",
+        in("ecx") msr,
+        in("eax") value as u32,
+        in("edx") (value >> 32) as u32,
+        options(nostack, preserves_flags)
+    );
+}
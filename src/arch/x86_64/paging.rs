@@ -1,47 +1,601 @@
-use core::arch::asm;
+//! Four-level paging, on top of the tables boot.S builds.
+//!
+//! Tables are read and written through the direct map (see layout.rs), which covers every frame
+//! the frame allocator hands out.
 
-#[repr(C)]
-#[derive(Clone, Copy)]
-pub struct PageEntry(pub u32);
+use core::{
+    arch::asm,
+    fmt,
+    ops::BitOr,
+    ptr,
+    sync::atomic::{AtomicBool, Ordering},
+};
 
-impl PageEntry {
-    pub fn new(addr: u32, flags: u32) -> Self {
-        Self((addr & 0xFFFFF000) | (flags & 0xFFF))
+use spin::Once;
+
+use crate::{
+    arch::x86_64::{
+        control_registers::{read_cr3, write_cr3},
+        cpu::{self, Feature, EFER_NO_EXECUTE_ENABLE},
+        msr::{read_msr, write_msr, IA32_EFER, IA32_PAT},
+    },
+    memory::{
+        frame_allocator::{self, Frame, FRAME_SIZE},
+        layout::{self, USER_SPACE_END},
+    },
+    sync::irq_spin_lock::{IrqSpinLock, IrqSpinLockGuard},
+};
+
+const ENTRY_COUNT: usize = 512;
+
+const PRESENT: u64 = 1 << 0;
+const WRITABLE: u64 = 1 << 1;
+const USER: u64 = 1 << 2;
+const WRITE_THROUGH: u64 = 1 << 3;
+const CACHE_DISABLE: u64 = 1 << 4;
+/// Set in a PDPT or PD entry that maps a page instead of pointing to a table.
+const HUGE_PAGE: u64 = 1 << 7;
+/// Selects the upper half of the PAT, at a different bit in 4 KiB and larger entries.
+const PAT_4K: u64 = 1 << 7;
+const PAT_HUGE: u64 = 1 << 12;
+const NO_EXECUTE: u64 = 1 << 63;
+const ADDRESS_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+
+/// WB, WT, UC-, UC, then WC, WT, UC-, UC. The first four are the power-on defaults that the
+/// boot.S mappings rely on, so only index 4, picked by the PAT bit alone, changes.
+const PAT_VALUE: u64 = 0x0007_0401_0007_0406;
+
+/// First PML4 entry of the higher half, shared by every address space.
+const KERNEL_PML4_START: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageSize {
+    Size4K,
+    Size2M,
+    Size1G,
+}
+
+impl PageSize {
+    pub const fn bytes(self) -> u64 {
+        match self {
+            PageSize::Size4K => 4 << 10,
+            PageSize::Size2M => 2 << 20,
+            PageSize::Size1G => 1 << 30,
+        }
+    }
+
+    /// Table level the page is mapped in, 1 being the page table and 4 the PML4.
+    const fn level(self) -> usize {
+        match self {
+            PageSize::Size4K => 1,
+            PageSize::Size2M => 2,
+            PageSize::Size1G => 3,
+        }
+    }
+
+    const fn from_level(level: usize) -> Self {
+        match level {
+            1 => PageSize::Size4K,
+            2 => PageSize::Size2M,
+            _ => PageSize::Size1G,
+        }
+    }
+}
+
+/// Access and caching attributes of a mapping. Pages are always readable by the kernel.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub struct PageFlags(u8);
+
+impl PageFlags {
+    pub const WRITABLE: Self = Self(1 << 0);
+    pub const USER: Self = Self(1 << 1);
+    /// Only enforced once `cpu::enable_features` turned on NX.
+    pub const NO_EXECUTE: Self = Self(1 << 2);
+    /// Uncached, for device registers.
+    pub const CACHE_DISABLE: Self = Self(1 << 3);
+    /// Writes are buffered and combined, for frame buffers. Uncached when the CPU has no PAT.
+    pub const WRITE_COMBINING: Self = Self(1 << 4);
+
+    const NAMES: [(PageFlags, &'static str); 5] = [
+        (PageFlags::WRITABLE, "writable"),
+        (PageFlags::USER, "user"),
+        (PageFlags::NO_EXECUTE, "no-execute"),
+        (PageFlags::CACHE_DISABLE, "cache-disable"),
+        (PageFlags::WRITE_COMBINING, "write-combining"),
+    ];
+
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    pub const fn contains(&self, other: PageFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    fn to_entry(self, size: PageSize) -> u64 {
+        let mut entry = PRESENT;
+
+        if self.contains(PageFlags::WRITABLE) {
+            entry |= WRITABLE;
+        }
+
+        if self.contains(PageFlags::USER) {
+            entry |= USER;
+        }
+
+        // The bit is reserved, and faults, while NX is off.
+        if self.contains(PageFlags::NO_EXECUTE)
+            && unsafe { read_msr(IA32_EFER) } & EFER_NO_EXECUTE_ENABLE != 0
+        {
+            entry |= NO_EXECUTE;
+        }
+
+        if self.contains(PageFlags::CACHE_DISABLE) {
+            entry |= CACHE_DISABLE | WRITE_THROUGH;
+        } else if self.contains(PageFlags::WRITE_COMBINING) {
+            if PAT_ENABLED.load(Ordering::Relaxed) {
+                entry |= pat_bit(size);
+            } else {
+                entry |= CACHE_DISABLE | WRITE_THROUGH;
+            }
+        }
+
+        if size != PageSize::Size4K {
+            entry |= HUGE_PAGE;
+        }
+
+        entry
+    }
+
+    fn from_entry(entry: u64, size: PageSize) -> Self {
+        let mut flags = PageFlags::empty();
+
+        for (bit, flag) in [
+            (WRITABLE, PageFlags::WRITABLE),
+            (USER, PageFlags::USER),
+            (NO_EXECUTE, PageFlags::NO_EXECUTE),
+            (CACHE_DISABLE, PageFlags::CACHE_DISABLE),
+            (pat_bit(size), PageFlags::WRITE_COMBINING),
+        ] {
+            if entry & bit != 0 {
+                flags = flags | flag;
+            }
+        }
+
+        flags
+    }
+}
+
+impl BitOr for PageFlags {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl fmt::Debug for PageFlags {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter
+            .debug_set()
+            .entries(
+                PageFlags::NAMES
+                    .iter()
+                    .filter(|(flag, _)| self.contains(*flag))
+                    .map(|(_, name)| name),
+            )
+            .finish()
     }
 }
 
-const PAGE_PRESENT: u32 = 1 << 0;
-const PAGE_WRITABLE: u32 = 1 << 1;
-#[allow(dead_code)]
-const PAGE_USER: u32 = 1 << 2;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapError {
+    /// The virtual address is not canonical, or a user page is in the kernel half.
+    InvalidAddress,
+    /// The virtual or physical address is not aligned to the page size.
+    Misaligned,
+    AlreadyMapped,
+    NotMapped,
+    /// A larger page already covers the address.
+    HugePageInTheWay,
+    /// 1 GiB pages are not supported by the CPU.
+    UnsupportedPageSize,
+    /// No frame was left for a page table, or for a page of `Mapper::map_new_frames`.
+    OutOfMemory,
+}
+
+/// Where a virtual address leads.
+#[derive(Debug, Clone, Copy)]
+pub struct Translation {
+    pub physical_address: u64,
+    pub size: PageSize,
+    pub flags: PageFlags,
+}
+
+/// A page whose mapping changed and may still be cached in the TLB. `flush` only invalidates it
+/// on the calling CPU; other CPUs using the same address space have to be sent `address`.
+#[must_use = "the TLB may still hold the old mapping"]
+pub struct Flush(u64);
+
+impl Flush {
+    pub fn address(&self) -> u64 {
+        self.0
+    }
+
+    pub fn flush(self) {
+        invalidate_page(self.0);
+    }
+
+    /// For changes to an address space that is not active on this CPU.
+    pub fn ignore(self) {}
+}
+
+/// Drops the TLB entries of the page that contains `address`, whatever its size, on the calling
+/// CPU.
+#[inline]
+pub fn invalidate_page(address: u64) {
+    unsafe { asm!("invlpg [{}]", in(reg) address, options(nostack, preserves_flags)) };
+}
+
+/// Drops every TLB entry of the calling CPU. No page is global, so reloading CR3 is enough.
+pub fn invalidate_all() {
+    unsafe { write_cr3(read_cr3()) };
+}
+
+/// Edits the tables of one address space, from the PML4 at `root`.
+pub struct Mapper {
+    root: u64,
+}
+
+impl Mapper {
+    /// Maps the page at `virtual_address` to `physical_address`, both aligned to `size`.
+    pub fn map(
+        &mut self,
+        virtual_address: u64,
+        physical_address: u64,
+        size: PageSize,
+        flags: PageFlags,
+    ) -> Result<Flush, MapError> {
+        check_address(virtual_address, flags)?;
+
+        if !virtual_address.is_multiple_of(size.bytes())
+            || !physical_address.is_multiple_of(size.bytes())
+            || physical_address & !ADDRESS_MASK != 0
+        {
+            return Err(MapError::Misaligned);
+        }
+
+        if size == PageSize::Size1G && !cpu::has(Feature::HugePages1G) {
+            return Err(MapError::UnsupportedPageSize);
+        }
+
+        let user = flags.contains(PageFlags::USER);
+        let entry = unsafe { self.entry_mut(virtual_address, size.level(), true, user)? };
 
-#[repr(align(4096))]
-pub struct PageTable([PageEntry; 1024]);
+        if *entry & PRESENT != 0 {
+            return Err(MapError::AlreadyMapped);
+        }
 
-static mut PAGE_TABLE: PageTable = PageTable([PageEntry(0); 1024]);
-static mut PAGE_DIRECTORY: PageTable = PageTable([PageEntry(0); 1024]);
+        *entry = physical_address | flags.to_entry(size);
 
-pub unsafe fn install_paging() {
-    for i in 0..1024 {
-        PAGE_TABLE.0[i] = PageEntry::new((i * 0x1000) as u32, PAGE_PRESENT | PAGE_WRITABLE);
+        Ok(Flush(virtual_address))
     }
 
-    #[allow(static_mut_refs)]
-    let page_table = &PAGE_TABLE as *const _ as u32;
+    /// Maps the 4 KiB pages of `[start, end)` that are not mapped yet to newly allocated frames,
+    /// in order. A failure comes with the address of the page that could not be mapped, the pages
+    /// below it stay mapped.
+    pub fn map_new_frames(
+        &mut self,
+        start: u64,
+        end: u64,
+        flags: PageFlags,
+    ) -> Result<(), (u64, MapError)> {
+        for page in (start..end).step_by(FRAME_SIZE as usize) {
+            if self.translate(page).is_some() {
+                continue;
+            }
 
-    PAGE_DIRECTORY.0[0] = PageEntry::new(page_table, PAGE_PRESENT | PAGE_WRITABLE);
+            let frame = frame_allocator::allocate().ok_or((page, MapError::OutOfMemory))?;
 
-    #[allow(static_mut_refs)]
-    let page_directory = &PAGE_DIRECTORY as *const _ as u32;
+            // The page was not mapped, so no CPU can have it in its TLB but this one.
+            match self.map(page, frame.start_address(), PageSize::Size4K, flags) {
+                Ok(flush) => flush.flush(),
+                Err(error) => {
+                    frame_allocator::free(frame);
+
+                    return Err((page, error));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Removes the page that starts at `virtual_address`, and returns the physical address it was
+    /// mapped to, for the caller to free. Tables left empty are kept.
+    pub fn unmap(&mut self, virtual_address: u64) -> Result<(u64, PageSize, Flush), MapError> {
+        let (_, size) = self.find(virtual_address).ok_or(MapError::NotMapped)?;
+
+        if !virtual_address.is_multiple_of(size.bytes()) {
+            return Err(MapError::Misaligned);
+        }
+
+        let entry = unsafe { self.entry_mut(virtual_address, size.level(), false, false)? };
+        let physical_address = *entry & ADDRESS_MASK & !(size.bytes() - 1);
+
+        *entry = 0;
+
+        Ok((physical_address, size, Flush(virtual_address)))
+    }
+
+    /// Replaces the flags of the page that starts at `virtual_address`.
+    pub fn protect(&mut self, virtual_address: u64, flags: PageFlags) -> Result<Flush, MapError> {
+        check_address(virtual_address, flags)?;
+
+        let (_, size) = self.find(virtual_address).ok_or(MapError::NotMapped)?;
+
+        if !virtual_address.is_multiple_of(size.bytes()) {
+            return Err(MapError::Misaligned);
+        }
+
+        let user = flags.contains(PageFlags::USER);
+        let entry = unsafe { self.entry_mut(virtual_address, size.level(), false, user)? };
+
+        *entry = (*entry & ADDRESS_MASK & !(size.bytes() - 1)) | flags.to_entry(size);
+
+        Ok(Flush(virtual_address))
+    }
+
+    pub fn translate(&self, virtual_address: u64) -> Option<Translation> {
+        let (entry, size) = self.find(virtual_address)?;
+        let base = entry & ADDRESS_MASK & !(size.bytes() - 1);
+
+        Some(Translation {
+            physical_address: base + virtual_address % size.bytes(),
+            size,
+            flags: PageFlags::from_entry(entry, size),
+        })
+    }
 
-    asm!("mov cr3, {0:e}", in(reg) page_directory, options(nostack, nomem));
+    /// The entry that maps `virtual_address`, and the size of its page.
+    fn find(&self, virtual_address: u64) -> Option<(u64, PageSize)> {
+        if !is_canonical(virtual_address) {
+            return None;
+        }
 
-    let mut cr0: u32;
-    asm!(
-      "mov {0:e}, cr0",
-      out(reg) cr0
-    );
-    cr0 |= 0x80000000; // set pg bit (31)
+        let mut current = unsafe { table(self.root) };
+
+        for level in (1..=4).rev() {
+            let entry = current[index(virtual_address, level)];
+
+            if entry & PRESENT == 0 {
+                return None;
+            }
+
+            if level == 1 || (level < 4 && entry & HUGE_PAGE != 0) {
+                return Some((entry, PageSize::from_level(level)));
+            }
+
+            current = unsafe { table(entry & ADDRESS_MASK) };
+        }
+
+        None
+    }
+
+    /// The entry for `virtual_address` in the table at `level`, with missing tables above it
+    /// allocated when `create` is set. `user` opens up the tables on the way to user mode.
+    ///
+    /// # Safety
+    ///
+    /// The returned entry aliases the table, which `&mut self` keeps from being edited elsewhere.
+    unsafe fn entry_mut(
+        &mut self,
+        virtual_address: u64,
+        level: usize,
+        create: bool,
+        user: bool,
+    ) -> Result<&mut u64, MapError> {
+        let mut table = table_mut(self.root);
+
+        for current in (level + 1..=4).rev() {
+            let entry = &mut table[index(virtual_address, current)];
+
+            if *entry & PRESENT == 0 {
+                if !create {
+                    return Err(MapError::NotMapped);
+                }
+
+                *entry = allocate_table()? | PRESENT | WRITABLE;
+            } else if *entry & HUGE_PAGE != 0 {
+                return Err(MapError::HugePageInTheWay);
+            }
+
+            // Access is checked at every level, the leaf entry decides the rest.
+            if user {
+                *entry |= USER;
+            }
+
+            table = table_mut(*entry & ADDRESS_MASK);
+        }
+
+        Ok(&mut table[index(virtual_address, level)])
+    }
+}
+
+/// A PML4 and the tables below it. The kernel half is the same in every address space.
+pub struct AddressSpace {
+    root: u64,
+    mapper: IrqSpinLock<Mapper>,
+}
+
+static KERNEL: Once<AddressSpace> = Once::new();
+static PAT_ENABLED: AtomicBool = AtomicBool::new(false);
+
+impl AddressSpace {
+    fn from_root(root: u64) -> Self {
+        Self {
+            root,
+            mapper: IrqSpinLock::new(Mapper { root }),
+        }
+    }
+
+    /// The address space built by boot.S, which the kernel runs in.
+    pub fn kernel() -> &'static AddressSpace {
+        KERNEL.r#try().expect("paging is not initialized")
+    }
+
+    /// An address space with an empty user half.
+    pub fn new() -> Result<Self, MapError> {
+        let root = allocate_table()?;
+        let kernel = unsafe { table(AddressSpace::kernel().root) };
+        let pml4 = unsafe { table_mut(root) };
+
+        pml4[KERNEL_PML4_START..].copy_from_slice(&kernel[KERNEL_PML4_START..]);
+
+        Ok(Self::from_root(root))
+    }
+
+    /// Physical address of the PML4, as loaded in CR3.
+    pub fn root(&self) -> u64 {
+        self.root
+    }
+
+    pub fn is_active(&self) -> bool {
+        read_cr3() & ADDRESS_MASK == self.root
+    }
+
+    /// Switches the calling CPU to this address space.
+    ///
+    /// # Safety
+    ///
+    /// The address space must outlive its use, and map whatever user memory is accessed next.
+    pub unsafe fn activate(&self) {
+        write_cr3(self.root);
+    }
+
+    pub fn mapper(&self) -> IrqSpinLockGuard<'_, Mapper> {
+        self.mapper.lock()
+    }
+
+    pub fn translate(&self, virtual_address: u64) -> Option<Translation> {
+        self.mapper().translate(virtual_address)
+    }
+}
+
+impl Drop for AddressSpace {
+    /// Frees the tables of the user half and the PML4. Mapped frames belong to whoever mapped them.
+    fn drop(&mut self) {
+        unsafe { free_tables(self.root, 4, 0..KERNEL_PML4_START) };
+    }
+}
+
+/// Looks `virtual_address` up in the tables the calling CPU runs on, whichever address space they
+/// belong to.
+pub fn translate(virtual_address: u64) -> Option<Translation> {
+    Mapper {
+        root: read_cr3() & ADDRESS_MASK,
+    }
+    .translate(virtual_address)
+}
+
+/// Sets up the PAT and takes over the tables boot.S built. Runs on the boot CPU, after the frame
+/// allocator.
+pub fn init() -> Result<(), MapError> {
+    init_pat();
+
+    let root = read_cr3() & ADDRESS_MASK;
+    let pml4 = unsafe { table_mut(root) };
+
+    // Other address spaces get copies of the kernel half of the PML4, so the tables below those
+    // entries have to exist before the first copy is made.
+    for entry in &mut pml4[KERNEL_PML4_START..] {
+        if *entry & PRESENT == 0 {
+            *entry = allocate_table()? | PRESENT | WRITABLE;
+        }
+    }
+
+    KERNEL.call_once(|| AddressSpace::from_root(root));
+
+    Ok(())
+}
+
+/// Adds write-combining to the PAT of the calling CPU. Every CPU has to run it, so their PATs agree.
+pub fn init_pat() {
+    if !cpu::has(Feature::Pat) {
+        return;
+    }
+
+    unsafe { write_msr(IA32_PAT, PAT_VALUE) };
+
+    invalidate_all();
+
+    PAT_ENABLED.store(true, Ordering::Relaxed);
+}
+
+fn is_canonical(address: u64) -> bool {
+    ((address << 16) as i64 >> 16) as u64 == address
+}
+
+fn check_address(virtual_address: u64, flags: PageFlags) -> Result<(), MapError> {
+    if !is_canonical(virtual_address)
+        || (flags.contains(PageFlags::USER) && virtual_address >= USER_SPACE_END as u64)
+    {
+        return Err(MapError::InvalidAddress);
+    }
+
+    Ok(())
+}
+
+const fn index(virtual_address: u64, level: usize) -> usize {
+    (virtual_address >> (12 + 9 * (level - 1))) as usize % ENTRY_COUNT
+}
+
+const fn pat_bit(size: PageSize) -> u64 {
+    match size {
+        PageSize::Size4K => PAT_4K,
+        _ => PAT_HUGE,
+    }
+}
+
+/// # Safety
+///
+/// `physical_address` must be a page table, and nothing may write to it while the reference lives.
+unsafe fn table(physical_address: u64) -> &'static [u64; ENTRY_COUNT] {
+    &*(layout::physical_to_virtual(physical_address as usize) as *const [u64; ENTRY_COUNT])
+}
+
+/// # Safety
+///
+/// `physical_address` must be a page table, and the reference the only one to it.
+unsafe fn table_mut(physical_address: u64) -> &'static mut [u64; ENTRY_COUNT] {
+    &mut *(layout::physical_to_virtual(physical_address as usize) as *mut [u64; ENTRY_COUNT])
+}
+
+/// A zeroed frame for a page table.
+fn allocate_table() -> Result<u64, MapError> {
+    let frame = frame_allocator::allocate().ok_or(MapError::OutOfMemory)?;
+    let address = frame.start_address();
+
+    unsafe {
+        ptr::write_bytes(
+            layout::physical_to_virtual(address as usize) as *mut u8,
+            0,
+            FRAME_SIZE as usize,
+        )
+    };
+
+    Ok(address)
+}
+
+/// Frees the tables below `entries` of the table at `level`, then the table itself.
+unsafe fn free_tables(physical_address: u64, level: usize, entries: core::ops::Range<usize>) {
+    if level > 1 {
+        for &entry in &table(physical_address)[entries] {
+            if entry & PRESENT != 0 && entry & HUGE_PAGE == 0 {
+                free_tables(entry & ADDRESS_MASK, level - 1, 0..ENTRY_COUNT);
+            }
+        }
+    }
 
-    asm!("mov cr0, {0:e}", in(reg) cr0);
+    frame_allocator::free(Frame::containing(physical_address));
 }
@@ -0,0 +1,148 @@
+//! Per-CPU data. Each CPU finds its own block through the GS base, which `swapgs` exchanges with
+//! the user GS base on entry from and exit to user mode (see interrupt_stubs.S).
+
+use core::{arch::asm, cell::UnsafeCell, mem::offset_of};
+
+use crate::arch::x86_64::{
+    gdt::TSS,
+    msr::{write_msr, IA32_GS_BASE, IA32_KERNEL_GS_BASE},
+    smp::MAX_CPUS,
+};
+
+/// What GS points to while in the kernel. Assembly code reads it at fixed offsets, see
+/// syscall_entry.S.
+#[repr(C)]
+pub struct CpuHeader {
+    /// Address of this header, so it can be found without reading the MSR.
+    self_pointer: u64,
+    index: u64,
+    /// User RSP, saved by the SYSCALL entry before switching stacks.
+    user_stack_pointer: u64,
+    /// Stack SYSCALL switches to, the same one the TSS gives interrupts from user mode.
+    kernel_stack_top: u64,
+}
+
+static mut HEADERS: [CpuHeader; MAX_CPUS] = [const {
+    CpuHeader {
+        self_pointer: 0,
+        index: 0,
+        user_stack_pointer: 0,
+        kernel_stack_top: 0,
+    }
+}; MAX_CPUS];
+
+/// Points GS to the per-CPU block of the calling CPU. Must run after the GDT is loaded, since
+/// loading the GS selector clears the GS base.
+#[allow(static_mut_refs)]
+pub fn init(cpu_index: usize) {
+    unsafe {
+        let header = &mut HEADERS[cpu_index];
+
+        header.self_pointer = header as *mut CpuHeader as u64;
+        header.index = cpu_index as u64;
+        header.kernel_stack_top = TSS.for_cpu(cpu_index).rsp[0];
+
+        write_msr(IA32_GS_BASE, header.self_pointer);
+        write_msr(IA32_KERNEL_GS_BASE, 0);
+    }
+}
+
+/// Registry index of the calling CPU (see smp.rs).
+#[inline]
+pub fn current_index() -> usize {
+    let index: usize;
+
+    unsafe {
+        asm!(
+            "mov {}, gs:[{}]",
+            out(reg) index,
+            const offset_of!(CpuHeader, index),
+            options(readonly, nostack, preserves_flags)
+        );
+    }
+
+    index
+}
+
+/// Sets the stack the calling CPU switches to on SYSCALL.
+#[inline]
+pub fn set_kernel_stack_top(stack_top: u64) {
+    unsafe {
+        asm!(
+            "mov gs:[{}], {}",
+            const offset_of!(CpuHeader, kernel_stack_top),
+            in(reg) stack_top,
+            options(nostack, preserves_flags)
+        );
+    }
+}
+
+/// Keeps each CPU's value on its own cache lines, so CPUs do not slow each other down by writing
+/// to their own values.
+#[repr(C, align(64))]
+pub struct CacheAligned<T>(UnsafeCell<T>);
+
+impl<T> CacheAligned<T> {
+    pub const fn new(value: T) -> Self {
+        Self(UnsafeCell::new(value))
+    }
+}
+
+/// A value for every CPU, declared with `percpu!`.
+pub struct PerCpu<T> {
+    values: [CacheAligned<T>; MAX_CPUS],
+}
+
+// A CPU only gets shared references to the values of other CPUs when `T` is `Sync`.
+unsafe impl<T: Send> Sync for PerCpu<T> {}
+
+impl<T> PerCpu<T> {
+    pub const fn new(values: [CacheAligned<T>; MAX_CPUS]) -> Self {
+        Self { values }
+    }
+
+    /// The value of the calling CPU. It can still be shared with interrupt handlers running on
+    /// the same CPU.
+    #[inline]
+    pub fn get(&self) -> &T {
+        unsafe { &*self.as_mut_ptr() }
+    }
+
+    #[inline]
+    pub fn as_mut_ptr(&self) -> *mut T {
+        self.as_mut_ptr_for(current_index())
+    }
+
+    pub fn as_mut_ptr_for(&self, cpu_index: usize) -> *mut T {
+        self.values[cpu_index].0.get()
+    }
+}
+
+impl<T: Sync> PerCpu<T> {
+    pub fn for_cpu(&self, cpu_index: usize) -> &T {
+        unsafe { &*self.as_mut_ptr_for(cpu_index) }
+    }
+}
+
+/// Declares statics with one value per CPU. Initializers must be constant.
+///
+/// ```ignore
+/// percpu! {
+///     static INTERRUPT_COUNT: AtomicU64 = AtomicU64::new(0);
+/// }
+///
+/// INTERRUPT_COUNT.get().fetch_add(1, Ordering::Relaxed);
+/// ```
+#[macro_export]
+macro_rules! percpu {
+    ($($(#[$attribute:meta])* $visibility:vis static $name:ident: $type:ty = $initializer:expr;)*) => {
+        $(
+            $(#[$attribute])*
+            $visibility static $name: $crate::arch::percpu::PerCpu<$type> =
+                $crate::arch::percpu::PerCpu::new(
+                    [const { $crate::arch::percpu::CacheAligned::new($initializer) };
+                        $crate::arch::smp::MAX_CPUS],
+                );
+        )*
+    };
+}
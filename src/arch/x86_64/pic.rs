@@ -0,0 +1,127 @@
+use crate::arch::x86_64::{interrupts, io};
+
+const MASTER_COMMAND_PORT: u16 = 0x20;
+const MASTER_DATA_PORT: u16 = 0x21;
+const SLAVE_COMMAND_PORT: u16 = 0xA0;
+const SLAVE_DATA_PORT: u16 = 0xA1;
+
+const ICW1_ICW4: u8 = 0x01; // ICW4 will be sent
+const ICW1_INIT: u8 = 0x10;
+const ICW4_8086: u8 = 0x01;
+
+const COMMAND_END_OF_INTERRUPT: u8 = 0x20;
+const COMMAND_READ_ISR: u8 = 0x0B;
+
+/// The BIOS leaves the master PIC at vectors 8-15, on top of CPU exceptions, so both PICs are
+/// moved right after the 32 exception vectors.
+pub const MASTER_VECTOR_OFFSET: u8 = 32;
+pub const SLAVE_VECTOR_OFFSET: u8 = MASTER_VECTOR_OFFSET + 8;
+
+pub const LINE_COUNT: u8 = 16;
+
+/// Master line the slave PIC is wired to.
+const CASCADE_LINE: u8 = 2;
+
+/// Remaps both PICs and masks every line except the cascade.
+pub fn init() {
+    unsafe {
+        io::outportb(MASTER_COMMAND_PORT, ICW1_INIT | ICW1_ICW4);
+        io::io_wait();
+        io::outportb(SLAVE_COMMAND_PORT, ICW1_INIT | ICW1_ICW4);
+        io::io_wait();
+
+        // ICW2: vector offsets
+        io::outportb(MASTER_DATA_PORT, MASTER_VECTOR_OFFSET);
+        io::io_wait();
+        io::outportb(SLAVE_DATA_PORT, SLAVE_VECTOR_OFFSET);
+        io::io_wait();
+
+        // ICW3: the master gets a bitmask of the lines with a slave, the slave gets its cascade identity.
+        io::outportb(MASTER_DATA_PORT, 1 << CASCADE_LINE);
+        io::io_wait();
+        io::outportb(SLAVE_DATA_PORT, CASCADE_LINE);
+        io::io_wait();
+
+        io::outportb(MASTER_DATA_PORT, ICW4_8086);
+        io::io_wait();
+        io::outportb(SLAVE_DATA_PORT, ICW4_8086);
+        io::io_wait();
+
+        io::outportb(MASTER_DATA_PORT, !(1 << CASCADE_LINE));
+        io::outportb(SLAVE_DATA_PORT, 0xFF);
+    }
+}
+
+/// Masks every line, used when interrupts are routed through the APIC instead.
+pub fn disable() {
+    unsafe {
+        io::outportb(MASTER_DATA_PORT, 0xFF);
+        io::outportb(SLAVE_DATA_PORT, 0xFF);
+    }
+}
+
+fn data_port(line: u8) -> (u16, u8) {
+    if line < 8 {
+        (MASTER_DATA_PORT, line)
+    } else {
+        (SLAVE_DATA_PORT, line - 8)
+    }
+}
+
+pub fn mask(line: u8) {
+    let (port, bit) = data_port(line);
+
+    interrupts::without_interrupts(|| unsafe {
+        io::outportb(port, io::inportb(port) | (1 << bit));
+    });
+}
+
+pub fn unmask(line: u8) {
+    let (port, bit) = data_port(line);
+
+    interrupts::without_interrupts(|| unsafe {
+        io::outportb(port, io::inportb(port) & !(1 << bit));
+    });
+}
+
+pub fn is_masked(line: u8) -> bool {
+    let (port, bit) = data_port(line);
+
+    unsafe { io::inportb(port) & (1 << bit) != 0 }
+}
+
+/// Lines currently being serviced, the master in the low byte.
+pub fn in_service_register() -> u16 {
+    unsafe {
+        io::outportb(MASTER_COMMAND_PORT, COMMAND_READ_ISR);
+        io::outportb(SLAVE_COMMAND_PORT, COMMAND_READ_ISR);
+
+        (io::inportb(SLAVE_COMMAND_PORT) as u16) << 8 | io::inportb(MASTER_COMMAND_PORT) as u16
+    }
+}
+
+/// A PIC raises its lowest priority line (IRQ 7 or IRQ 15) when the line that triggered an
+/// interrupt is deasserted before the CPU acknowledges it. Those show up without their bit
+/// in the in-service register.
+pub fn is_spurious(line: u8) -> bool {
+    (line == 7 || line == 15) && in_service_register() & (1 << line) == 0
+}
+
+pub fn end_of_interrupt(line: u8) {
+    unsafe {
+        if line >= 8 {
+            io::outportb(SLAVE_COMMAND_PORT, COMMAND_END_OF_INTERRUPT);
+        }
+
+        io::outportb(MASTER_COMMAND_PORT, COMMAND_END_OF_INTERRUPT);
+    }
+}
+
+/// A spurious IRQ 15 was still a real interrupt for the master, through the cascade line.
+pub fn acknowledge_spurious(line: u8) {
+    if line == 15 {
+        unsafe {
+            io::outportb(MASTER_COMMAND_PORT, COMMAND_END_OF_INTERRUPT);
+        }
+    }
+}
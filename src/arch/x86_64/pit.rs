@@ -0,0 +1,59 @@
+use crate::arch::x86_64::io;
+
+const CHANNEL_0_PORT: u16 = 0x40;
+const CHANNEL_2_PORT: u16 = 0x42;
+const COMMAND_PORT: u16 = 0x43;
+/// Gate of channel 2 (bit 0), PC speaker (bit 1) and output of channel 2 (bit 5).
+const SYSTEM_CONTROL_PORT_B: u16 = 0x61;
+
+const GATE_2: u8 = 1 << 0;
+const SPEAKER: u8 = 1 << 1;
+const OUTPUT_2: u8 = 1 << 5;
+
+// Command byte: channel (bits 6-7), access mode (bits 4-5), operating mode (bits 1-3), binary.
+const COMMAND_CHANNEL_0: u8 = 0b00 << 6;
+const COMMAND_CHANNEL_2: u8 = 0b10 << 6;
+const COMMAND_LOW_HIGH_BYTE: u8 = 0b11 << 4;
+const MODE_INTERRUPT_ON_TERMINAL_COUNT: u8 = 0b000 << 1;
+const MODE_RATE_GENERATOR: u8 = 0b010 << 1;
+
+/// Input clock of every channel, in Hz.
+pub const FREQUENCY: u64 = 1_193_182;
+
+/// IRQ line of channel 0.
+pub const IRQ_LINE: u8 = 0;
+
+/// Makes channel 0 raise IRQ 0 every `divisor` input clock cycles.
+pub fn set_periodic(divisor: u16) {
+    unsafe {
+        io::outportb(
+            COMMAND_PORT,
+            COMMAND_CHANNEL_0 | COMMAND_LOW_HIGH_BYTE | MODE_RATE_GENERATOR,
+        );
+        io::outportb(CHANNEL_0_PORT, divisor as u8);
+        io::outportb(CHANNEL_0_PORT, (divisor >> 8) as u8);
+    }
+}
+
+/// Busy waits for `cycles` input clock cycles using channel 2, which is not wired to an
+/// interrupt. Used to calibrate other clocks.
+pub fn wait_cycles(cycles: u16) {
+    unsafe {
+        let control = io::inportb(SYSTEM_CONTROL_PORT_B);
+
+        // Keep the speaker quiet while the channel runs.
+        io::outportb(SYSTEM_CONTROL_PORT_B, (control & !SPEAKER) | GATE_2);
+
+        io::outportb(
+            COMMAND_PORT,
+            COMMAND_CHANNEL_2 | COMMAND_LOW_HIGH_BYTE | MODE_INTERRUPT_ON_TERMINAL_COUNT,
+        );
+        io::outportb(CHANNEL_2_PORT, cycles as u8);
+        io::outportb(CHANNEL_2_PORT, (cycles >> 8) as u8);
+
+        // The output goes high once the count reaches zero.
+        while io::inportb(SYSTEM_CONTROL_PORT_B) & OUTPUT_2 == 0 {}
+
+        io::outportb(SYSTEM_CONTROL_PORT_B, control);
+    }
+}
@@ -1,69 +1,42 @@
-use crate::{arch::x86_64::gdt, screen::vga};
 use core::arch::asm;
 
-use crate::println;
+use crate::arch::x86_64::gdt;
 
-fn get_cs_register() -> u16 {
+/// IF set, IOPL 0: user code gets interrupts but no I/O ports, it goes through system calls.
+const USER_RFLAGS: u64 = 1 << 9 | 1 << 1;
+
+/// Privilege level of the running code, from the RPL of CS.
+pub fn current_ring() -> u16 {
     let cs: u16;
 
     unsafe {
-        asm!("mov {cs:x}, cs", cs = out(reg) cs);
+        asm!("mov {:x}, cs", out(reg) cs, options(nomem, nostack, preserves_flags));
     }
 
-    return cs;
-}
-
-fn current_ring() -> u16 {
-    return get_cs_register() & 0b11;
+    cs & 0b11
 }
 
-#[no_mangle]
-pub extern "C" fn user_code() {
-    vga::VGA_SCREEN.lock().clear_screen();
-    println!("Estamos no ring: {}", current_ring());
-    loop {}
-}
-
-fn enable_io_instructions() {
-    unsafe {
-        core::arch::asm!(
-            "pushfd",
-            "pop eax",
-            "or eax, {mask}",
-            "push eax",
-            "popfd",
-            mask = const (0b11 << 12), // O campo IOPL está nos bits 12 e 13
-            options(nostack, preserves_flags)
-        );
-    }
-}
-
-pub unsafe fn switch_to_ring_3() -> ! {
-    // Utilizamos instruções de I/O para mostrar uma mensagem na tela com VGA.
-    // No entanto, essas instruções não são permitidas para serem executadas no ring 3.
-    // Ao alterarmos o IOPL (I/O privilege) para 3, permitimos código executando em ring 3
-    // utilizar portas de I/O.
-    // O ideal é criarmos uma API de syscall para mostrar mensagem na tela, mas estamos apenas testando agora
-    // a troca para ring 3.
-    enable_io_instructions();
-
+/// Drops to ring 3 at `entry` with RSP at `stack_top`.
+///
+/// # Safety
+///
+/// `entry` and the stack must be mapped user accessible in the current address space.
+pub unsafe fn enter_user_mode(entry: u64, stack_top: u64) -> ! {
     asm!(
-        "mov ds, {data_segment_selector:x}",
-        "mov es, {data_segment_selector:x}",
-        "mov fs, {data_segment_selector:x}",
-        "mov gs, {data_segment_selector:x}",
-        "mov {tmp:e}, esp",
-        "push {data_segment_selector:e}", // SS (DS)
-        "push {tmp:e}",                   // Current ESP
-        "pushfd",                         // EFLAGS
-        "push {code_segment_selector:e}", // CS
-        "push {user_code}",
-        "iretd",
-        tmp = out(reg) _,
-        data_segment_selector = in(reg) gdt::USER_DATA_SEGMENT_SELECTOR as u32,
-        code_segment_selector = in(reg) gdt::USER_CODE_SEGMENT_SELECTOR as u32,
-        user_code = in(reg) user_code
+        // Interrupts from user mode swap back to the kernel GS (see interrupt_stubs.S).
+        "cli",
+        "swapgs",
+        "push {data_segment_selector}", // SS
+        "push {stack_top}",             // RSP
+        "push {rflags}",                // RFLAGS
+        "push {code_segment_selector}", // CS
+        "push {entry}",                 // RIP
+        "iretq",
+        data_segment_selector = in(reg) gdt::USER_DATA_SEGMENT_SELECTOR as u64,
+        stack_top = in(reg) stack_top,
+        rflags = in(reg) USER_RFLAGS,
+        code_segment_selector = in(reg) gdt::USER_CODE_SEGMENT_SELECTOR as u64,
+        entry = in(reg) entry,
+        options(noreturn)
     );
-
-    loop {}
 }
@@ -0,0 +1,119 @@
+use crate::{arch::x86_64::io, time::date::DateTime};
+
+const INDEX_PORT: u16 = 0x70;
+const DATA_PORT: u16 = 0x71;
+
+const REGISTER_SECONDS: u8 = 0x00;
+const REGISTER_MINUTES: u8 = 0x02;
+const REGISTER_HOURS: u8 = 0x04;
+const REGISTER_DAY: u8 = 0x07;
+const REGISTER_MONTH: u8 = 0x08;
+const REGISTER_YEAR: u8 = 0x09;
+const REGISTER_STATUS_A: u8 = 0x0A;
+const REGISTER_STATUS_B: u8 = 0x0B;
+
+const STATUS_A_UPDATE_IN_PROGRESS: u8 = 1 << 7;
+const STATUS_B_24_HOUR: u8 = 1 << 1;
+const STATUS_B_BINARY: u8 = 1 << 2;
+
+const HOUR_PM: u8 = 1 << 7;
+
+/// Register values as stored by the RTC, in whatever format it is configured for.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct RawTime {
+    second: u8,
+    minute: u8,
+    hour: u8,
+    day: u8,
+    month: u8,
+    year: u8,
+    century: u8,
+}
+
+fn read_register(register: u8) -> u8 {
+    unsafe {
+        io::outportb(INDEX_PORT, register);
+        io::inportb(DATA_PORT)
+    }
+}
+
+fn is_updating() -> bool {
+    read_register(REGISTER_STATUS_A) & STATUS_A_UPDATE_IN_PROGRESS != 0
+}
+
+fn read_raw(century_register: u8) -> RawTime {
+    while is_updating() {
+        core::hint::spin_loop();
+    }
+
+    RawTime {
+        second: read_register(REGISTER_SECONDS),
+        minute: read_register(REGISTER_MINUTES),
+        hour: read_register(REGISTER_HOURS),
+        day: read_register(REGISTER_DAY),
+        month: read_register(REGISTER_MONTH),
+        year: read_register(REGISTER_YEAR),
+        century: match century_register {
+            0 => 0,
+            register => read_register(register),
+        },
+    }
+}
+
+fn from_bcd(value: u8) -> u8 {
+    (value >> 4) * 10 + (value & 0x0F)
+}
+
+/// Reads the date and time kept by the RTC, which is assumed to run in UTC.
+/// `century_register` is the CMOS index reported by the FADT, 0 if there is none, in which case
+/// the 21st century is assumed.
+pub fn read(century_register: u8) -> DateTime {
+    // An update may start right after the update-in-progress check, so read until two
+    // consecutive reads agree.
+    let mut raw = read_raw(century_register);
+
+    loop {
+        let again = read_raw(century_register);
+
+        if again == raw {
+            break;
+        }
+
+        raw = again;
+    }
+
+    let status_b = read_register(REGISTER_STATUS_B);
+    let is_pm = raw.hour & HOUR_PM != 0;
+
+    raw.hour &= !HOUR_PM;
+
+    if status_b & STATUS_B_BINARY == 0 {
+        raw.second = from_bcd(raw.second);
+        raw.minute = from_bcd(raw.minute);
+        raw.hour = from_bcd(raw.hour);
+        raw.day = from_bcd(raw.day);
+        raw.month = from_bcd(raw.month);
+        raw.year = from_bcd(raw.year);
+        raw.century = from_bcd(raw.century);
+    }
+
+    // 12-hour mode counts 12, 1, ..., 11.
+    if status_b & STATUS_B_24_HOUR == 0 {
+        raw.hour %= 12;
+
+        if is_pm {
+            raw.hour += 12;
+        }
+    }
+
+    let century = if raw.century == 0 { 20 } else { raw.century };
+
+    DateTime {
+        year: century as u16 * 100 + raw.year as u16,
+        month: raw.month,
+        day: raw.day,
+        hour: raw.hour,
+        minute: raw.minute,
+        second: raw.second,
+    }
+}
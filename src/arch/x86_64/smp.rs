@@ -0,0 +1,264 @@
+use core::{
+    ptr,
+    sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use crate::{
+    arch::x86_64::{
+        control_registers::{read_cr0, read_cr3, read_cr4, write_cr3},
+        cpu,
+        gdt::{self, Stack},
+        idt, interrupts, local_apic,
+        msr::{read_msr, IA32_EFER},
+        paging, percpu, syscall,
+    },
+    memory::layout,
+    time,
+};
+
+pub const MAX_CPUS: usize = 16;
+
+/// Registry index of the CPU that booted the kernel.
+pub const BSP_INDEX: usize = 0;
+
+/// Physical page the trampoline is copied to, must match ap_trampoline.S. Startup IPIs can only
+/// point below 1 MiB. This page and the next one are assumed to be unused conventional memory.
+const AP_TRAMPOLINE_ADDRESS: usize = 0x8000;
+/// PML4 used by the APs until they run in the higher half.
+const AP_PAGE_TABLE_ADDRESS: usize = 0x9000;
+
+/// PML4 entry of the direct map of physical memory, see boot.S.
+const PHYSICAL_MEMORY_PML4_INDEX: usize = 256;
+
+const EFER_LONG_MODE_ACTIVE: u64 = 1 << 10;
+
+// Delays of the INIT-SIPI-SIPI sequence from the Intel MultiProcessor Specification.
+const INIT_DELAY: Duration = Duration::from_millis(10);
+const STARTUP_DELAY: Duration = Duration::from_micros(200);
+const STARTUP_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Read by ap_trampoline.S, field by field.
+#[repr(C)]
+struct TrampolineData {
+    page_table: u64,
+    cr4: u64,
+    efer: u64,
+    cr0: u64,
+    stack_top: u64,
+    entry: u64,
+    cpu_index: u64,
+    kernel_page_table: u64,
+}
+
+extern "C" {
+    static ap_trampoline_start: u8;
+    static ap_trampoline_data: u8;
+    static ap_trampoline_end: u8;
+}
+
+#[derive(Debug)]
+pub enum SmpError {
+    /// APs are started through the local APIC, which is not in use.
+    ApicDisabled,
+    TooManyCpus(u32),
+    Timeout(u32),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Cpu {
+    pub index: usize,
+    pub apic_id: u32,
+    pub online: bool,
+}
+
+struct CpuSlot {
+    apic_id: AtomicU32,
+    online: AtomicBool,
+}
+
+static CPUS: [CpuSlot; MAX_CPUS] = [const {
+    CpuSlot {
+        apic_id: AtomicU32::new(0),
+        online: AtomicBool::new(false),
+    }
+}; MAX_CPUS];
+static CPU_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+percpu! {
+    /// Stack the APs start on. The BSP keeps the one from boot.S.
+    static BOOT_STACK: Stack = Stack::new();
+}
+
+/// Registers the BSP, once its local APIC is set up.
+pub fn init_bsp() {
+    CPUS[BSP_INDEX]
+        .apic_id
+        .store(local_apic::id(), Ordering::Release);
+    CPUS[BSP_INDEX].online.store(true, Ordering::Release);
+    CPU_COUNT.store(BSP_INDEX + 1, Ordering::Release);
+}
+
+/// CPUs started so far, the BSP included. A CPU that does not come online gives its slot back.
+pub fn cpu_count() -> usize {
+    CPU_COUNT.load(Ordering::Acquire)
+}
+
+pub fn online_count() -> usize {
+    cpus().filter(|cpu| cpu.online).count()
+}
+
+pub fn cpu(index: usize) -> Option<Cpu> {
+    if index >= cpu_count() {
+        return None;
+    }
+
+    Some(Cpu {
+        index,
+        apic_id: CPUS[index].apic_id.load(Ordering::Acquire),
+        online: CPUS[index].online.load(Ordering::Acquire),
+    })
+}
+
+pub fn cpus() -> impl Iterator<Item = Cpu> {
+    (0..cpu_count()).filter_map(cpu)
+}
+
+/// Registry index of the calling CPU.
+pub fn current_index() -> usize {
+    percpu::current_index()
+}
+
+/// Starts the CPUs with the given APIC IDs one after the other, skipping the BSP. Returns how many
+/// came online.
+pub fn start_application_processors(apic_ids: impl Iterator<Item = u32>) -> usize {
+    if local_apic::mode() == local_apic::Mode::Disabled {
+        println!(
+            "warning: cannot start the other CPUs: {:?}",
+            SmpError::ApicDisabled
+        );
+        return 0;
+    }
+
+    install_trampoline();
+
+    let bsp_apic_id = local_apic::id();
+    let mut started = 0;
+
+    for apic_id in apic_ids.filter(|&apic_id| apic_id != bsp_apic_id) {
+        match start(apic_id) {
+            Ok(_) => started += 1,
+            Err(error) => println!("warning: failed to start CPU: {:?}", error),
+        }
+    }
+
+    started
+}
+
+/// Copies the trampoline below 1 MiB and builds its page table: the kernel mappings of the BSP,
+/// plus the first 4 GiB identity mapped by reusing the PDP of the direct map.
+fn install_trampoline() {
+    unsafe {
+        let start = &raw const ap_trampoline_start;
+        let length = &raw const ap_trampoline_end as usize - start as usize;
+
+        ptr::copy_nonoverlapping(
+            start,
+            layout::physical_to_virtual(AP_TRAMPOLINE_ADDRESS) as *mut u8,
+            length,
+        );
+
+        let kernel_page_table =
+            layout::physical_to_virtual(read_cr3() as usize & !0xFFF) as *const u64;
+        let page_table = layout::physical_to_virtual(AP_PAGE_TABLE_ADDRESS) as *mut u64;
+
+        ptr::copy_nonoverlapping(kernel_page_table, page_table, 512);
+        page_table.write(page_table.add(PHYSICAL_MEMORY_PML4_INDEX).read());
+    }
+}
+
+fn start(apic_id: u32) -> Result<usize, SmpError> {
+    let index = cpu_count();
+
+    if index >= MAX_CPUS {
+        return Err(SmpError::TooManyCpus(apic_id));
+    }
+
+    CPUS[index].apic_id.store(apic_id, Ordering::Release);
+    CPUS[index].online.store(false, Ordering::Release);
+    CPU_COUNT.store(index + 1, Ordering::Release);
+
+    let data = TrampolineData {
+        page_table: AP_PAGE_TABLE_ADDRESS as u64,
+        cr4: read_cr4(),
+        efer: unsafe { read_msr(IA32_EFER) } & !EFER_LONG_MODE_ACTIVE,
+        cr0: read_cr0(),
+        stack_top: BOOT_STACK.for_cpu(index).top(),
+        entry: ap_enter as *const () as u64,
+        cpu_index: index as u64,
+        kernel_page_table: read_cr3(),
+    };
+
+    unsafe {
+        let offset =
+            &raw const ap_trampoline_data as usize - &raw const ap_trampoline_start as usize;
+        let destination = layout::physical_to_virtual(AP_TRAMPOLINE_ADDRESS + offset);
+
+        ptr::write_volatile(destination as *mut TrampolineData, data);
+    }
+
+    let page = (AP_TRAMPOLINE_ADDRESS >> 12) as u8;
+
+    local_apic::send_init_ipi(apic_id);
+    time::sleep(INIT_DELAY);
+
+    // A second startup IPI is only needed if the first one got lost, the CPU ignores it otherwise.
+    local_apic::send_startup_ipi(apic_id, page);
+    time::sleep(STARTUP_DELAY);
+
+    if !CPUS[index].online.load(Ordering::Acquire) {
+        local_apic::send_startup_ipi(apic_id, page);
+    }
+
+    let deadline = time::monotonic_now() + STARTUP_TIMEOUT;
+
+    while !CPUS[index].online.load(Ordering::Acquire) {
+        if time::monotonic_now() > deadline {
+            // Hold the CPU in reset, so that it cannot wake up late and boot with the data the
+            // next CPU gets in the trampoline and slot.
+            local_apic::send_init_ipi(apic_id);
+
+            CPUS[index].apic_id.store(0, Ordering::Release);
+            CPUS[index].online.store(false, Ordering::Release);
+            CPU_COUNT.store(index, Ordering::Release);
+
+            return Err(SmpError::Timeout(apic_id));
+        }
+
+        core::hint::spin_loop();
+    }
+
+    Ok(index)
+}
+
+/// Entry point of the APs, called by ap_trampoline.S on the AP's stack.
+extern "C" fn ap_enter(cpu_index: usize, kernel_page_table: u64) -> ! {
+    // Drop the identity mapping of the trampoline.
+    unsafe { write_cr3(kernel_page_table) };
+
+    gdt::install(cpu_index);
+    percpu::init(cpu_index);
+    idt::load();
+    cpu::enable_features();
+    paging::init_pat();
+    syscall::init();
+    local_apic::init();
+
+    CPUS[cpu_index].online.store(true, Ordering::Release);
+
+    interrupts::enable();
+
+    loop {
+        interrupts::halt();
+    }
+}
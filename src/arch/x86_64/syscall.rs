@@ -0,0 +1,85 @@
+//! The SYSCALL/SYSRET entry. The system calls themselves are in src/syscall.
+
+use crate::{
+    arch::x86_64::{
+        cpu::{self, Feature},
+        gdt::{KERNEL_CODE_SEGMENT_SELECTOR, USER_DATA_SEGMENT_SELECTOR},
+        interrupts,
+        msr::{read_msr, write_msr, IA32_EFER, IA32_FMASK, IA32_LSTAR, IA32_STAR},
+    },
+    syscall,
+};
+
+const EFER_SYSCALL_ENABLE: u64 = 1;
+
+const RFLAGS_TRAP: u64 = 1 << 8;
+const RFLAGS_INTERRUPT_ENABLE: u64 = 1 << 9;
+const RFLAGS_DIRECTION: u64 = 1 << 10;
+const RFLAGS_ALIGNMENT_CHECK: u64 = 1 << 18;
+
+/// Registers saved by syscall_entry.S, in stack order.
+#[repr(C)]
+#[derive(Debug)]
+pub struct SyscallFrame {
+    pub r9: u64,
+    pub r8: u64,
+    pub r10: u64,
+    pub rdx: u64,
+    pub rsi: u64,
+    pub rdi: u64,
+    /// Syscall number.
+    pub rax: u64,
+    /// User RIP, saved by SYSCALL in RCX.
+    pub rcx: u64,
+    /// User RFLAGS, saved by SYSCALL in R11.
+    pub r11: u64,
+    pub user_stack_pointer: u64,
+}
+
+extern "C" {
+    fn syscall_entry();
+}
+
+/// Enables SYSCALL on the calling CPU. Returns false if the CPU does not support it.
+pub fn init() -> bool {
+    if !cpu::has(Feature::Syscall) {
+        return false;
+    }
+
+    // SYSCALL loads CS from STAR[47:32] and SS from 8 bytes above it. SYSRET to 64-bit mode loads
+    // SS from STAR[63:48] + 8 and CS from STAR[63:48] + 16, which is why user data comes right
+    // before user code in the GDT.
+    let sysret_base = USER_DATA_SEGMENT_SELECTOR as u64 - 8;
+    let star = sysret_base << 48 | (KERNEL_CODE_SEGMENT_SELECTOR as u64) << 32;
+
+    unsafe {
+        write_msr(IA32_STAR, star);
+        write_msr(IA32_LSTAR, syscall_entry as *const () as u64);
+        // Enter with interrupts off until the stack is switched, and with SMAP checks on.
+        write_msr(
+            IA32_FMASK,
+            RFLAGS_TRAP | RFLAGS_INTERRUPT_ENABLE | RFLAGS_DIRECTION | RFLAGS_ALIGNMENT_CHECK,
+        );
+        write_msr(IA32_EFER, read_msr(IA32_EFER) | EFER_SYSCALL_ENABLE);
+    }
+
+    true
+}
+
+#[no_mangle]
+extern "C" fn syscall_dispatch(frame: &mut SyscallFrame) -> u64 {
+    // The kernel stack is in use now, so interrupts can come in while the call runs.
+    if frame.r11 & RFLAGS_INTERRUPT_ENABLE != 0 {
+        interrupts::enable();
+    }
+
+    let arguments = [
+        frame.rdi, frame.rsi, frame.rdx, frame.r10, frame.r8, frame.r9,
+    ];
+    let result = syscall::dispatch(frame.rax, &arguments);
+
+    // Interrupts must stay off from the GS swap back until SYSRET.
+    interrupts::disable();
+
+    result
+}
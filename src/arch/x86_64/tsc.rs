@@ -0,0 +1,35 @@
+use core::arch::asm;
+
+use crate::arch::x86_64::cpu::{self, Feature};
+
+/// Reads the time stamp counter. `lfence` keeps it from being executed ahead of earlier
+/// instructions.
+#[inline]
+pub fn read() -> u64 {
+    let low: u32;
+    let high: u32;
+
+    unsafe {
+        asm!("lfence", "rdtsc", out("eax") low, out("edx") high, options(nomem, nostack, preserves_flags));
+    }
+
+    (high as u64) << 32 | low as u64
+}
+
+/// Whether the TSC runs at a constant rate in every power state, which is required to use it
+/// as a clock.
+pub fn is_invariant() -> bool {
+    cpu::has(Feature::InvariantTsc)
+}
+
+/// Measures the TSC frequency in Hz against `wait`, which must busy wait for
+/// `reference_nanoseconds`.
+pub fn calibrate(reference_nanoseconds: u64, wait: impl FnOnce()) -> u64 {
+    let start = read();
+
+    wait();
+
+    let end = read();
+
+    ((end - start) as u128 * 1_000_000_000 / reference_nanoseconds as u128) as u64
+}
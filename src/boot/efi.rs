@@ -0,0 +1,122 @@
+//! Tables left behind by UEFI firmware. Boot services are terminated by the time the kernel runs,
+//! so only the system table and its configuration tables are read.
+
+use core::fmt;
+
+use crate::memory::layout;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Guid([u8; 16]);
+
+impl Guid {
+    /// The first three fields are stored little endian, the last one as is.
+    pub const fn new(data1: u32, data2: u16, data3: u16, data4: [u8; 8]) -> Self {
+        let data1 = data1.to_le_bytes();
+        let data2 = data2.to_le_bytes();
+        let data3 = data3.to_le_bytes();
+
+        Self([
+            data1[0], data1[1], data1[2], data1[3], data2[0], data2[1], data3[0], data3[1],
+            data4[0], data4[1], data4[2], data4[3], data4[4], data4[5], data4[6], data4[7],
+        ])
+    }
+}
+
+impl fmt::Debug for Guid {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes = &self.0;
+
+        write!(
+            formatter,
+            "{:08x}-{:04x}-{:04x}-{:02x}{:02x}-",
+            u32::from_le_bytes(bytes[0..4].try_into().unwrap()),
+            u16::from_le_bytes(bytes[4..6].try_into().unwrap()),
+            u16::from_le_bytes(bytes[6..8].try_into().unwrap()),
+            bytes[8],
+            bytes[9]
+        )?;
+
+        for byte in &bytes[10..] {
+            write!(formatter, "{:02x}", byte)?;
+        }
+
+        Ok(())
+    }
+}
+
+pub const ACPI_20_TABLE_GUID: Guid = Guid::new(
+    0x8868_E871,
+    0xE4F1,
+    0x11D3,
+    [0xBC, 0x22, 0x00, 0x80, 0xC7, 0x3C, 0x88, 0x81],
+);
+pub const ACPI_TABLE_GUID: Guid = Guid::new(
+    0xEB9D_2D30,
+    0x2D88,
+    0x11D3,
+    [0x9A, 0x16, 0x00, 0x90, 0x27, 0x3F, 0xC1, 0x4D],
+);
+
+const SYSTEM_TABLE_SIGNATURE: &[u8; 8] = b"IBI SYST";
+const SYSTEM_TABLE_SIZE: usize = 120;
+const CONFIGURATION_TABLE_ENTRY_SIZE: usize = 24;
+
+/// The EFI system table of 64-bit firmware.
+#[derive(Debug, Clone, Copy)]
+pub struct SystemTable {
+    pub revision: u32,
+    pub firmware_revision: u32,
+    pub runtime_services: u64,
+    configuration_table: u64,
+    configuration_table_count: usize,
+}
+
+impl SystemTable {
+    /// # Safety
+    ///
+    /// `physical_address` must point to memory that stays valid, such as the system table
+    /// handed over by the boot loader.
+    pub unsafe fn load(physical_address: u64) -> Option<Self> {
+        let bytes = layout::physical_slice(physical_address, SYSTEM_TABLE_SIZE)?;
+
+        if bytes[0..8] != *SYSTEM_TABLE_SIGNATURE {
+            return None;
+        }
+
+        Some(Self {
+            revision: read_u32(bytes, 8),
+            firmware_revision: read_u32(bytes, 32),
+            runtime_services: read_u64(bytes, 88),
+            configuration_table_count: read_u64(bytes, 104) as usize,
+            configuration_table: read_u64(bytes, 112),
+        })
+    }
+
+    /// GUIDs and physical addresses of the tables published by the firmware (ACPI, SMBIOS...).
+    pub fn configuration_tables(&self) -> impl Iterator<Item = (Guid, u64)> {
+        // A count too large to be real leaves the table empty.
+        let bytes = self
+            .configuration_table_count
+            .checked_mul(CONFIGURATION_TABLE_ENTRY_SIZE)
+            .and_then(|length| unsafe { layout::physical_slice(self.configuration_table, length) });
+
+        bytes
+            .unwrap_or(&[])
+            .chunks_exact(CONFIGURATION_TABLE_ENTRY_SIZE)
+            .map(|entry| (Guid(entry[0..16].try_into().unwrap()), read_u64(entry, 16)))
+    }
+
+    pub fn find_configuration_table(&self, guid: &Guid) -> Option<u64> {
+        self.configuration_tables()
+            .find(|(table_guid, _)| table_guid == guid)
+            .map(|(_, address)| address)
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
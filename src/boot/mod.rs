@@ -0,0 +1,3 @@
+pub mod efi;
+pub mod multiboot2;
+pub mod params;
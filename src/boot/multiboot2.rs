@@ -0,0 +1,598 @@
+use core::{ffi::CStr, slice};
+
+/// Value left in EAX by a Multiboot2 compliant boot loader.
+pub const BOOTLOADER_MAGIC: u32 = 0x36d76289;
+
+const TAG_TYPE_END: u32 = 0;
+const TAG_TYPE_COMMAND_LINE: u32 = 1;
+const TAG_TYPE_BOOT_LOADER_NAME: u32 = 2;
+const TAG_TYPE_MODULE: u32 = 3;
+const TAG_TYPE_BASIC_MEMORY_INFORMATION: u32 = 4;
+const TAG_TYPE_MEMORY_MAP: u32 = 6;
+const TAG_TYPE_FRAMEBUFFER: u32 = 8;
+const TAG_TYPE_EFI32_SYSTEM_TABLE: u32 = 11;
+const TAG_TYPE_EFI64_SYSTEM_TABLE: u32 = 12;
+const TAG_TYPE_ACPI_OLD_RSDP: u32 = 14;
+const TAG_TYPE_ACPI_NEW_RSDP: u32 = 15;
+const TAG_TYPE_EFI_MEMORY_MAP: u32 = 17;
+const TAG_TYPE_EFI_BOOT_SERVICES_NOT_TERMINATED: u32 = 18;
+const TAG_TYPE_EFI32_IMAGE_HANDLE: u32 = 19;
+const TAG_TYPE_EFI64_IMAGE_HANDLE: u32 = 20;
+
+// Every tag starts with a `type` and a `size` field, both u32.
+const TAG_HEADER_SIZE: usize = 8;
+
+#[derive(Debug)]
+pub enum BootInformationError {
+    NullPointer,
+    Misaligned,
+    InvalidSize(u32),
+    MissingEndTag,
+}
+
+/// Boot information structure handed over by the boot loader in EBX.
+///
+/// The structure is a fixed 8 byte header (`total_size` and a reserved field)
+/// followed by a list of 8-byte aligned tags terminated by an end tag.
+pub struct BootInformation<'a> {
+    bytes: &'a [u8],
+}
+
+impl BootInformation<'static> {
+    /// # Safety
+    ///
+    /// `address` must point to a boot information structure that stays mapped
+    /// and untouched for the whole lifetime of the kernel.
+    pub unsafe fn load(address: usize) -> Result<Self, BootInformationError> {
+        if address == 0 {
+            return Err(BootInformationError::NullPointer);
+        }
+
+        if address & 0x7 != 0 {
+            return Err(BootInformationError::Misaligned);
+        }
+
+        let total_size = (address as *const u32).read();
+
+        // The smallest valid structure is the header followed by the end tag.
+        if (total_size as usize) < 2 * TAG_HEADER_SIZE || total_size & 0x7 != 0 {
+            return Err(BootInformationError::InvalidSize(total_size));
+        }
+
+        let bytes = slice::from_raw_parts(address as *const u8, total_size as usize);
+        let end_tag = &bytes[bytes.len() - TAG_HEADER_SIZE..];
+
+        if read_u32(end_tag, 0) != Some(TAG_TYPE_END)
+            || read_u32(end_tag, 4) != Some(TAG_HEADER_SIZE as u32)
+        {
+            return Err(BootInformationError::MissingEndTag);
+        }
+
+        Ok(Self { bytes })
+    }
+}
+
+impl<'a> BootInformation<'a> {
+    pub fn start_address(&self) -> usize {
+        self.bytes.as_ptr() as usize
+    }
+
+    pub fn end_address(&self) -> usize {
+        self.start_address() + self.bytes.len()
+    }
+
+    pub fn total_size(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn tags(&self) -> TagIter<'a> {
+        TagIter {
+            bytes: self.bytes,
+            offset: TAG_HEADER_SIZE,
+        }
+    }
+
+    pub fn command_line(&self) -> Option<&'a str> {
+        self.tags().find_map(|tag| match tag {
+            Tag::CommandLine(command_line) => Some(command_line),
+            _ => None,
+        })
+    }
+
+    pub fn boot_loader_name(&self) -> Option<&'a str> {
+        self.tags().find_map(|tag| match tag {
+            Tag::BootLoaderName(name) => Some(name),
+            _ => None,
+        })
+    }
+
+    pub fn basic_memory_information(&self) -> Option<BasicMemoryInformation> {
+        self.tags().find_map(|tag| match tag {
+            Tag::BasicMemoryInformation(information) => Some(information),
+            _ => None,
+        })
+    }
+
+    pub fn memory_map(&self) -> Option<MemoryMap<'a>> {
+        self.tags().find_map(|tag| match tag {
+            Tag::MemoryMap(memory_map) => Some(memory_map),
+            _ => None,
+        })
+    }
+
+    pub fn modules(&self) -> impl Iterator<Item = Module<'a>> {
+        self.tags().filter_map(|tag| match tag {
+            Tag::Module(module) => Some(module),
+            _ => None,
+        })
+    }
+
+    pub fn framebuffer(&self) -> Option<Framebuffer<'a>> {
+        self.tags().find_map(|tag| match tag {
+            Tag::Framebuffer(framebuffer) => Some(framebuffer),
+            _ => None,
+        })
+    }
+
+    /// Physical address of the EFI system table, when booted from 64-bit UEFI firmware.
+    pub fn efi_system_table(&self) -> Option<u64> {
+        self.tags().find_map(|tag| match tag {
+            Tag::EfiSystemTable64(address) => Some(address),
+            _ => None,
+        })
+    }
+
+    pub fn efi_memory_map(&self) -> Option<EfiMemoryMap<'a>> {
+        self.tags().find_map(|tag| match tag {
+            Tag::EfiMemoryMap(memory_map) => Some(memory_map),
+            _ => None,
+        })
+    }
+
+    /// Whether the kernel was started by UEFI firmware, where legacy BIOS structures such as
+    /// the EBDA and the VGA text buffer are not available.
+    pub fn is_efi_boot(&self) -> bool {
+        self.tags().any(|tag| {
+            matches!(
+                tag,
+                Tag::EfiSystemTable32(_) | Tag::EfiSystemTable64(_) | Tag::EfiMemoryMap(_)
+            )
+        })
+    }
+
+    /// Whether the VGA text buffer at 0xB8000 can be written to.
+    pub fn has_vga_text_mode(&self) -> bool {
+        !self.is_efi_boot()
+            && self.framebuffer().is_none_or(|framebuffer| {
+                matches!(framebuffer.framebuffer_type, FramebufferType::EgaText)
+            })
+    }
+
+    /// Returns a copy of the ACPI RSDP, preferring the ACPI 2.0+ one when both are present.
+    pub fn rsdp(&self) -> Option<Rsdp<'a>> {
+        let mut old_rsdp = None;
+
+        for tag in self.tags() {
+            match tag {
+                Tag::AcpiNewRsdp(bytes) => return Some(Rsdp::V2(bytes)),
+                Tag::AcpiOldRsdp(bytes) => old_rsdp = Some(Rsdp::V1(bytes)),
+                _ => {}
+            }
+        }
+
+        old_rsdp
+    }
+}
+
+pub struct TagIter<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Iterator for TagIter<'a> {
+    type Item = Tag<'a>;
+
+    fn next(&mut self) -> Option<Tag<'a>> {
+        let tag_type = read_u32(self.bytes, self.offset)?;
+        let size = read_u32(self.bytes, self.offset + 4)? as usize;
+
+        if tag_type == TAG_TYPE_END
+            || size < TAG_HEADER_SIZE
+            || self.offset + size > self.bytes.len()
+        {
+            self.offset = self.bytes.len();
+
+            return None;
+        }
+
+        let data = &self.bytes[self.offset + TAG_HEADER_SIZE..self.offset + size];
+
+        // Tags are padded so that the next one starts at an 8-byte boundary.
+        self.offset += (size + 7) & !7;
+
+        Some(Tag::parse(tag_type, data))
+    }
+}
+
+pub enum Tag<'a> {
+    CommandLine(&'a str),
+    BootLoaderName(&'a str),
+    Module(Module<'a>),
+    BasicMemoryInformation(BasicMemoryInformation),
+    MemoryMap(MemoryMap<'a>),
+    Framebuffer(Framebuffer<'a>),
+    EfiSystemTable32(u32),
+    EfiSystemTable64(u64),
+    AcpiOldRsdp(&'a [u8]),
+    AcpiNewRsdp(&'a [u8]),
+    EfiMemoryMap(EfiMemoryMap<'a>),
+    /// The kernel asked to keep boot services running, `ExitBootServices` was not called.
+    EfiBootServicesNotTerminated,
+    EfiImageHandle32(u32),
+    EfiImageHandle64(u64),
+    Unknown {
+        tag_type: u32,
+        data: &'a [u8],
+    },
+}
+
+impl<'a> Tag<'a> {
+    fn parse(tag_type: u32, data: &'a [u8]) -> Self {
+        let tag = match tag_type {
+            TAG_TYPE_COMMAND_LINE => read_str(data).map(Tag::CommandLine),
+            TAG_TYPE_BOOT_LOADER_NAME => read_str(data).map(Tag::BootLoaderName),
+            TAG_TYPE_MODULE => Module::parse(data).map(Tag::Module),
+            TAG_TYPE_BASIC_MEMORY_INFORMATION => {
+                BasicMemoryInformation::parse(data).map(Tag::BasicMemoryInformation)
+            }
+            TAG_TYPE_MEMORY_MAP => MemoryMap::parse(data).map(Tag::MemoryMap),
+            TAG_TYPE_FRAMEBUFFER => Framebuffer::parse(data).map(Tag::Framebuffer),
+            TAG_TYPE_EFI32_SYSTEM_TABLE => read_u32(data, 0).map(Tag::EfiSystemTable32),
+            TAG_TYPE_EFI64_SYSTEM_TABLE => read_u64(data, 0).map(Tag::EfiSystemTable64),
+            TAG_TYPE_ACPI_OLD_RSDP => Some(Tag::AcpiOldRsdp(data)),
+            TAG_TYPE_ACPI_NEW_RSDP => Some(Tag::AcpiNewRsdp(data)),
+            TAG_TYPE_EFI_MEMORY_MAP => EfiMemoryMap::parse(data).map(Tag::EfiMemoryMap),
+            TAG_TYPE_EFI_BOOT_SERVICES_NOT_TERMINATED => Some(Tag::EfiBootServicesNotTerminated),
+            TAG_TYPE_EFI32_IMAGE_HANDLE => read_u32(data, 0).map(Tag::EfiImageHandle32),
+            TAG_TYPE_EFI64_IMAGE_HANDLE => read_u64(data, 0).map(Tag::EfiImageHandle64),
+            _ => None,
+        };
+
+        // Truncated or otherwise malformed tags are handed out raw instead of being dropped.
+        tag.unwrap_or(Tag::Unknown { tag_type, data })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Module<'a> {
+    pub start_address: u32,
+    pub end_address: u32,
+    pub command_line: &'a str,
+}
+
+impl<'a> Module<'a> {
+    fn parse(data: &'a [u8]) -> Option<Self> {
+        Some(Self {
+            start_address: read_u32(data, 0)?,
+            end_address: read_u32(data, 4)?,
+            command_line: read_str(data.get(8..)?)?,
+        })
+    }
+
+    pub fn size(&self) -> usize {
+        (self.end_address - self.start_address) as usize
+    }
+}
+
+/// Amount of lower (starting at 0) and upper (starting at 1 MiB) memory in KiB.
+#[derive(Debug, Clone, Copy)]
+pub struct BasicMemoryInformation {
+    pub lower: u32,
+    pub upper: u32,
+}
+
+impl BasicMemoryInformation {
+    fn parse(data: &[u8]) -> Option<Self> {
+        Some(Self {
+            lower: read_u32(data, 0)?,
+            upper: read_u32(data, 4)?,
+        })
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct MemoryMap<'a> {
+    entry_size: usize,
+    entries: &'a [u8],
+}
+
+impl<'a> MemoryMap<'a> {
+    // base_addr (u64) + length (u64) + type (u32) + reserved (u32)
+    const MINIMUM_ENTRY_SIZE: usize = 24;
+
+    fn parse(data: &'a [u8]) -> Option<Self> {
+        let entry_size = read_u32(data, 0)? as usize;
+
+        if entry_size < Self::MINIMUM_ENTRY_SIZE {
+            return None;
+        }
+
+        Some(Self {
+            entry_size,
+            entries: data.get(8..)?,
+        })
+    }
+
+    pub fn areas(&self) -> impl Iterator<Item = MemoryArea> + 'a {
+        self.entries
+            .chunks_exact(self.entry_size)
+            .map(|entry| MemoryArea {
+                base_address: read_u64(entry, 0).unwrap(),
+                length: read_u64(entry, 8).unwrap(),
+                area_type: MemoryAreaType::from(read_u32(entry, 16).unwrap()),
+            })
+    }
+
+    pub fn available_areas(&self) -> impl Iterator<Item = MemoryArea> + 'a {
+        self.areas()
+            .filter(|area| area.area_type == MemoryAreaType::Available)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MemoryArea {
+    pub base_address: u64,
+    pub length: u64,
+    pub area_type: MemoryAreaType,
+}
+
+impl MemoryArea {
+    pub fn end_address(&self) -> u64 {
+        self.base_address + self.length
+    }
+}
+
+/// Memory map as returned by the firmware's `GetMemoryMap`, more detailed than `MemoryMap`.
+#[derive(Clone, Copy)]
+pub struct EfiMemoryMap<'a> {
+    descriptor_size: usize,
+    pub descriptor_version: u32,
+    descriptors: &'a [u8],
+}
+
+impl<'a> EfiMemoryMap<'a> {
+    // type (u32) + padding (u32) + physical start, virtual start, page count and attributes (u64)
+    const MINIMUM_DESCRIPTOR_SIZE: usize = 40;
+
+    fn parse(data: &'a [u8]) -> Option<Self> {
+        let descriptor_size = read_u32(data, 0)? as usize;
+
+        if descriptor_size < Self::MINIMUM_DESCRIPTOR_SIZE {
+            return None;
+        }
+
+        Some(Self {
+            descriptor_size,
+            descriptor_version: read_u32(data, 4)?,
+            descriptors: data.get(8..)?,
+        })
+    }
+
+    pub fn descriptors(&self) -> impl Iterator<Item = EfiMemoryDescriptor> + 'a {
+        self.descriptors
+            .chunks_exact(self.descriptor_size)
+            .map(|descriptor| EfiMemoryDescriptor {
+                memory_type: EfiMemoryType::from(read_u32(descriptor, 0).unwrap()),
+                physical_start: read_u64(descriptor, 8).unwrap(),
+                virtual_start: read_u64(descriptor, 16).unwrap(),
+                page_count: read_u64(descriptor, 24).unwrap(),
+                attributes: read_u64(descriptor, 32).unwrap(),
+            })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct EfiMemoryDescriptor {
+    pub memory_type: EfiMemoryType,
+    pub physical_start: u64,
+    pub virtual_start: u64,
+    /// Number of 4 KiB pages.
+    pub page_count: u64,
+    pub attributes: u64,
+}
+
+impl EfiMemoryDescriptor {
+    /// `None` for a descriptor that runs past the end of the address space.
+    pub fn end_address(&self) -> Option<u64> {
+        self.page_count
+            .checked_mul(4096)?
+            .checked_add(self.physical_start)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EfiMemoryType {
+    Reserved,
+    LoaderCode,
+    LoaderData,
+    BootServicesCode,
+    BootServicesData,
+    RuntimeServicesCode,
+    RuntimeServicesData,
+    Conventional,
+    Unusable,
+    AcpiReclaimable,
+    AcpiNvs,
+    MemoryMappedIo,
+    MemoryMappedIoPortSpace,
+    PalCode,
+    Persistent,
+    Unknown(u32),
+}
+
+impl EfiMemoryType {
+    /// Whether the memory is free once boot services are terminated. Boot loader memory holds the
+    /// kernel and the boot information, so it is only free once those are no longer needed.
+    pub fn is_free_after_boot_services(&self) -> bool {
+        matches!(
+            self,
+            EfiMemoryType::Conventional
+                | EfiMemoryType::BootServicesCode
+                | EfiMemoryType::BootServicesData
+        )
+    }
+}
+
+impl From<u32> for EfiMemoryType {
+    fn from(value: u32) -> Self {
+        match value {
+            0 => EfiMemoryType::Reserved,
+            1 => EfiMemoryType::LoaderCode,
+            2 => EfiMemoryType::LoaderData,
+            3 => EfiMemoryType::BootServicesCode,
+            4 => EfiMemoryType::BootServicesData,
+            5 => EfiMemoryType::RuntimeServicesCode,
+            6 => EfiMemoryType::RuntimeServicesData,
+            7 => EfiMemoryType::Conventional,
+            8 => EfiMemoryType::Unusable,
+            9 => EfiMemoryType::AcpiReclaimable,
+            10 => EfiMemoryType::AcpiNvs,
+            11 => EfiMemoryType::MemoryMappedIo,
+            12 => EfiMemoryType::MemoryMappedIoPortSpace,
+            13 => EfiMemoryType::PalCode,
+            14 => EfiMemoryType::Persistent,
+            _ => EfiMemoryType::Unknown(value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryAreaType {
+    Available,
+    Reserved,
+    AcpiReclaimable,
+    AcpiNvs,
+    Defective,
+    Unknown(u32),
+}
+
+impl From<u32> for MemoryAreaType {
+    fn from(value: u32) -> Self {
+        match value {
+            1 => MemoryAreaType::Available,
+            2 => MemoryAreaType::Reserved,
+            3 => MemoryAreaType::AcpiReclaimable,
+            4 => MemoryAreaType::AcpiNvs,
+            5 => MemoryAreaType::Defective,
+            _ => MemoryAreaType::Unknown(value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Framebuffer<'a> {
+    pub address: u64,
+    pub pitch: u32,
+    pub width: u32,
+    pub height: u32,
+    pub bits_per_pixel: u8,
+    pub framebuffer_type: FramebufferType<'a>,
+}
+
+impl<'a> Framebuffer<'a> {
+    fn parse(data: &'a [u8]) -> Option<Self> {
+        let color_information = data.get(24..)?;
+
+        let framebuffer_type = match *data.get(21)? {
+            0 => {
+                let color_count = read_u16(color_information, 0)? as usize;
+
+                FramebufferType::Indexed {
+                    palette: color_information.get(2..2 + color_count * 3)?,
+                }
+            }
+            1 => FramebufferType::Rgb {
+                red: ColorField::parse(color_information.get(0..2)?),
+                green: ColorField::parse(color_information.get(2..4)?),
+                blue: ColorField::parse(color_information.get(4..6)?),
+            },
+            2 => FramebufferType::EgaText,
+            _ => return None,
+        };
+
+        Some(Self {
+            address: read_u64(data, 0)?,
+            pitch: read_u32(data, 8)?,
+            width: read_u32(data, 12)?,
+            height: read_u32(data, 16)?,
+            bits_per_pixel: *data.get(20)?,
+            framebuffer_type,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum FramebufferType<'a> {
+    /// `palette` holds one red, green and blue byte per color.
+    Indexed {
+        palette: &'a [u8],
+    },
+    Rgb {
+        red: ColorField,
+        green: ColorField,
+        blue: ColorField,
+    },
+    EgaText,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ColorField {
+    pub position: u8,
+    pub size: u8,
+}
+
+impl ColorField {
+    fn parse(bytes: &[u8]) -> Self {
+        Self {
+            position: bytes[0],
+            size: bytes[1],
+        }
+    }
+}
+
+/// Copy of the ACPI Root System Description Pointer made by the boot loader.
+#[derive(Debug, Clone, Copy)]
+pub enum Rsdp<'a> {
+    V1(&'a [u8]),
+    V2(&'a [u8]),
+}
+
+impl<'a> Rsdp<'a> {
+    pub fn bytes(&self) -> &'a [u8] {
+        match self {
+            Rsdp::V1(bytes) | Rsdp::V2(bytes) => bytes,
+        }
+    }
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        bytes.get(offset..offset + 2)?.try_into().unwrap(),
+    ))
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        bytes.get(offset..offset + 4)?.try_into().unwrap(),
+    ))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        bytes.get(offset..offset + 8)?.try_into().unwrap(),
+    ))
+}
+
+fn read_str(bytes: &[u8]) -> Option<&str> {
+    CStr::from_bytes_until_nul(bytes).ok()?.to_str().ok()
+}
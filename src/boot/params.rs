@@ -0,0 +1,188 @@
+use core::str::FromStr;
+
+use spin::Once;
+
+use crate::network::repr::ipv4::{IpAddress, Ipv4Cidr};
+
+/// Parameters read from the kernel command line, e.g.
+/// `multiboot2 /boot/kernel.bin log=debug console=serial ip=10.0.2.15/24 gw=10.0.2.2`.
+#[derive(Debug, Clone, Copy)]
+pub struct BootParameters {
+    pub log_level: LogLevel,
+    pub console: Console,
+    pub ip: Ipv4Cidr,
+    pub gateway: Option<IpAddress>,
+    pub nic: NetworkDriver,
+}
+
+impl BootParameters {
+    pub const DEFAULT: BootParameters = BootParameters {
+        log_level: LogLevel::Info,
+        console: Console {
+            serial: true,
+            vga: true,
+        },
+        ip: Ipv4Cidr::new(IpAddress([192, 168, 100, 2]), 24),
+        gateway: None,
+        nic: NetworkDriver::E1000,
+    };
+}
+
+impl Default for BootParameters {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidValue;
+
+struct Parameter {
+    key: &'static str,
+    parse: fn(&mut BootParameters, &str) -> Result<(), InvalidValue>,
+}
+
+static REGISTRY: &[Parameter] = &[
+    Parameter {
+        key: "log",
+        parse: |parameters, value| {
+            parameters.log_level = value.parse()?;
+            Ok(())
+        },
+    },
+    Parameter {
+        key: "console",
+        parse: |parameters, value| {
+            parameters.console = value.parse()?;
+            Ok(())
+        },
+    },
+    Parameter {
+        key: "ip",
+        parse: |parameters, value| {
+            parameters.ip = value.parse().map_err(|_| InvalidValue)?;
+            Ok(())
+        },
+    },
+    Parameter {
+        key: "gw",
+        parse: |parameters, value| {
+            parameters.gateway = Some(value.parse().map_err(|_| InvalidValue)?);
+            Ok(())
+        },
+    },
+    Parameter {
+        key: "nic",
+        parse: |parameters, value| {
+            parameters.nic = value.parse()?;
+            Ok(())
+        },
+    },
+];
+
+static PARAMETERS: Once<BootParameters> = Once::new();
+
+/// Parses the command line once. Unknown keys and invalid values are reported and
+/// leave the corresponding default in place.
+pub fn init(command_line: &str) -> &'static BootParameters {
+    PARAMETERS.call_once(|| parse(command_line))
+}
+
+/// Parameters of this boot, or the defaults if the command line was not parsed yet.
+pub fn get() -> &'static BootParameters {
+    PARAMETERS.r#try().unwrap_or(&BootParameters::DEFAULT)
+}
+
+fn parse(command_line: &str) -> BootParameters {
+    let mut parameters = BootParameters::DEFAULT;
+
+    for (index, word) in command_line.split_whitespace().enumerate() {
+        // Some boot loaders pass the kernel path as the first word.
+        if index == 0 && word.starts_with('/') {
+            continue;
+        }
+
+        let (key, value) = word.split_once('=').unwrap_or((word, ""));
+
+        match REGISTRY.iter().find(|parameter| parameter.key == key) {
+            Some(parameter) => {
+                if (parameter.parse)(&mut parameters, value).is_err() {
+                    println!("warning: invalid value `{value}` for boot parameter `{key}`, using default");
+                }
+            }
+            None => println!("warning: unknown boot parameter `{key}`"),
+        }
+    }
+
+    parameters
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl FromStr for LogLevel {
+    type Err = InvalidValue;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "error" => Ok(LogLevel::Error),
+            "warn" => Ok(LogLevel::Warn),
+            "info" => Ok(LogLevel::Info),
+            "debug" => Ok(LogLevel::Debug),
+            "trace" => Ok(LogLevel::Trace),
+            _ => Err(InvalidValue),
+        }
+    }
+}
+
+/// Outputs `print!` writes to, given as a comma separated list (`console=serial,vga`).
+#[derive(Debug, Clone, Copy)]
+pub struct Console {
+    pub serial: bool,
+    pub vga: bool,
+}
+
+impl FromStr for Console {
+    type Err = InvalidValue;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut console = Console {
+            serial: false,
+            vga: false,
+        };
+
+        for output in value.split(',') {
+            match output {
+                "serial" => console.serial = true,
+                "vga" => console.vga = true,
+                _ => return Err(InvalidValue),
+            }
+        }
+
+        Ok(console)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetworkDriver {
+    E1000,
+    None,
+}
+
+impl FromStr for NetworkDriver {
+    type Err = InvalidValue;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "e1000" => Ok(NetworkDriver::E1000),
+            "none" => Ok(NetworkDriver::None),
+            _ => Err(InvalidValue),
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use core::{slice, str};
+
+// Bounds of the table generated by scripts/symbol_table.sh. Both are equal when the kernel was
+// linked without it.
+extern "C" {
+    static kernel_symbol_table_start: u8;
+    static kernel_symbol_table_end: u8;
+}
+
+const ENTRY_SIZE: usize = 16;
+
+fn table() -> &'static [u8] {
+    let start = &raw const kernel_symbol_table_start;
+    let end = &raw const kernel_symbol_table_end;
+
+    unsafe { slice::from_raw_parts(start, end as usize - start as usize) }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+/// Finds the function containing `address`, returning its demangled name and the offset of `address` into it.
+pub fn symbolize(address: u64) -> Option<(&'static str, u64)> {
+    let table = table();
+
+    if table.len() < 8 {
+        return None;
+    }
+
+    let count = read_u64(table, 0) as usize;
+    let (entries, _) = table
+        .get(8..8 + count * ENTRY_SIZE)?
+        .as_chunks::<ENTRY_SIZE>();
+    let names = &table[8 + count * ENTRY_SIZE..];
+
+    // Entries are sorted by address, the symbol is the last one starting at or before `address`.
+    let entry = match entries.partition_point(|entry| read_u64(entry, 0) <= address) {
+        0 => return None,
+        index => &entries[index - 1],
+    };
+
+    let symbol_address = read_u64(entry, 0);
+    let name_offset = read_u32(entry, 8) as usize;
+    let name_length = read_u32(entry, 12) as usize;
+    let name = str::from_utf8(names.get(name_offset..name_offset + name_length)?).ok()?;
+
+    Some((name, address - symbol_address))
+}
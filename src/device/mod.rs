@@ -0,0 +1,5 @@
+#[macro_use]
+pub mod vga;
+#[macro_use]
+pub mod serial;
+pub mod pci;
@@ -1,6 +1,23 @@
-use core::slice;
+use core::{slice, time::Duration};
 
-use crate::{arch::io, pci::EtherType::Arp};
+use crate::{
+    arch::io,
+    boot::params::{self, NetworkDriver},
+    memory::layout,
+    sync::irq_spin_lock::IrqSpinLock,
+    thread, time,
+};
+
+/// Transmit Inter Packet Gap register.
+const REGISTER_TIPG: usize = 0x0410;
+const TIPG_IPGR1_BIT: u32 = 10;
+const TIPG_IPGR2_BIT: u32 = 20;
+
+const RESET_TIMEOUT: Duration = Duration::from_millis(10);
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Held across each CONFIG_ADDRESS/CONFIG_DATA pair so accesses do not interleave.
+static CONFIGURATION_SPACE: IrqSpinLock<()> = IrqSpinLock::new(());
 
 fn read_configuration_register_long(bus: u8, device: u8, function: u8, offset: u32) -> u32 {
     let address = 0x80000000
@@ -9,6 +26,8 @@ fn read_configuration_register_long(bus: u8, device: u8, function: u8, offset: u
         | (function as u32) << 8
         | offset & 0xFFFC;
 
+    let _configuration_space = CONFIGURATION_SPACE.lock();
+
     unsafe {
         io::outportl(0xCF8, address);
         io::inportl(0xCFC)
@@ -22,6 +41,8 @@ fn write_configuration_register_long(bus: u8, device: u8, function: u8, offset:
         | (function as u32) << 8
         | offset & 0xFFFC;
 
+    let _configuration_space = CONFIGURATION_SPACE.lock();
+
     unsafe {
         io::outportl(0xCF8, address);
         io::outportl(0xCFC, value);
@@ -96,9 +117,10 @@ fn visit_function(bus: u8, device: u8, function: u8) {
         visit_bus(secondary_bus_number as u8);
     }
 
-    if base_class == 0x02 && subclass == 0x00 {
+    if base_class == 0x02 && subclass == 0x00 && params::get().nic == NetworkDriver::E1000 {
         let mmio_address =
             read_configuration_register_long(bus, device, function, 0x10) & 0xFFFFFFF0;
+        let mmio_address = layout::physical_to_virtual(mmio_address as usize);
         let mac_memory_address = mmio_address + 0x5400;
 
         unsafe {
@@ -137,7 +159,14 @@ fn visit_function(bus: u8, device: u8, function: u8) {
             mmio_ptr.write_volatile(ctrl | (1 << 26));
 
             // Wait the bit disabled
-            while mmio_ptr.read_volatile() & 1 << 26 != 0 {}
+            let reset_deadline = time::monotonic_now() + RESET_TIMEOUT;
+
+            while mmio_ptr.read_volatile() & 1 << 26 != 0 {
+                if time::monotonic_now() > reset_deadline {
+                    println!("e1000: reset timed out");
+                    return;
+                }
+            }
 
             let ctrl = mmio_ptr.read_volatile();
 
@@ -151,11 +180,18 @@ fn visit_function(bus: u8, device: u8, function: u8) {
             ((mac_memory_address + 4) as *mut u32).write_volatile(u32::from_le_bytes(rah0));
 
             // Create ring
-            for i in 0..32 {
-                RECEIVE_RING.0[i].buffer_address = RECEIVE_RING_BUFFERS.0[i].as_ptr() as u64;
-            }
+            let receive_ring_address = {
+                let mut receive_ring = RECEIVE_RING.lock();
+
+                for i in 0..32 {
+                    // The NIC works with physical addresses.
+                    receive_ring.0[i].buffer_address =
+                        layout::virtual_to_physical(RECEIVE_RING_BUFFERS.0[i].as_ptr() as usize)
+                            as u64;
+                }
 
-            let receive_ring_address = &RECEIVE_RING as *const _ as u64;
+                layout::virtual_to_physical(&*receive_ring as *const _ as usize) as u64
+            };
 
             (mmio_ptr.byte_add(0x2800)).write_volatile(receive_ring_address as u32); // RDBAL
             (mmio_ptr.byte_add(0x2804)).write_volatile((receive_ring_address >> 32) as u32); // RDBAH
@@ -171,11 +207,17 @@ fn visit_function(bus: u8, device: u8, function: u8) {
             (mmio_ptr.add(0x0100 / 4)).write_volatile(rctl);
 
             // Enable transmission
-            for i in 0..32 {
-                TRANSMIT_RING.0[i].buffer_address = TRANSMIT_RING_BUFFERS.0[i].as_ptr() as u64;
-            }
+            let transmit_ring_address = {
+                let mut transmit_ring = TRANSMIT_RING.lock();
 
-            let transmit_ring_address = &TRANSMIT_RING as *const _ as u64;
+                for i in 0..32 {
+                    transmit_ring.0[i].buffer_address =
+                        layout::virtual_to_physical(TRANSMIT_RING_BUFFERS.0[i].as_ptr() as usize)
+                            as u64;
+                }
+
+                layout::virtual_to_physical(&*transmit_ring as *const _ as usize) as u64
+            };
 
             (mmio_ptr.byte_add(0x3800)).write_volatile(transmit_ring_address as u32); // TDBAL
             (mmio_ptr.byte_add(0x3804)).write_volatile((transmit_ring_address >> 32) as u32); // TDBAH
@@ -190,135 +232,161 @@ fn visit_function(bus: u8, device: u8, function: u8) {
 
             (mmio_ptr.byte_add(0x0400)).write_volatile(tctl);
 
-            const TIPG: u16 = 10 | 8 << TIPG_IPGR1_BIT | 6 << TIPG_IPGR2_BIT;
+            const TIPG: u32 = 10 | 8 << TIPG_IPGR1_BIT | 6 << TIPG_IPGR2_BIT;
 
             (mmio_ptr.byte_add(REGISTER_TIPG)).write_volatile(TIPG); // Transmit Inter Packet Gap
 
-            let mut receive_current = 0;
-            let mut transmit_current = 0;
-            let mut sent_arp_reply = false;
+            if let Err(error) = thread::spawn("e1000", receive_loop, mmio_address) {
+                println!("e1000: failed to start the receive thread: {:?}", error);
+            }
+        }
+    }
+}
 
-            loop {
-                let descriptor = &mut RECEIVE_RING.0[receive_current];
+/// Answers the first ARP request received, polling the receive ring every `POLL_INTERVAL`.
+fn receive_loop(mmio_address: usize) {
+    let mac_memory_address = mmio_address + 0x5400;
 
-                if core::ptr::addr_of!(descriptor.status).read_volatile() & 1 == 0 {
-                    continue;
-                }
+    unsafe {
+        let mmio_ptr = mmio_address as *mut u32;
+
+        let mut receive_current = 0;
+        let mut transmit_current = 0;
+        let mut sent_arp_reply = false;
+
+        loop {
+            // The lock keeps interrupts off, so it is only held to look at the descriptor. The buffer
+            // stays ours until the descriptor is handed back below.
+            let (buffer_address, length, status) = {
+                let receive_ring = RECEIVE_RING.lock();
+                let descriptor = &receive_ring.0[receive_current];
+
+                (
+                    descriptor.buffer_address,
+                    descriptor.length,
+                    core::ptr::addr_of!(descriptor.status).read_volatile(),
+                )
+            };
+
+            if status & 1 == 0 {
+                thread::sleep(POLL_INTERVAL);
+
+                continue;
+            }
+
+            let ptr = layout::physical_to_virtual(buffer_address as usize) as *const u8;
+
+            let slice = slice::from_raw_parts(ptr, 6);
+            let mut destination_address = [0; 6];
+            destination_address.copy_from_slice(slice);
 
-                let length = descriptor.length;
+            let slice = slice::from_raw_parts(ptr.byte_add(6), 6);
+            let mut source_address = [0; 6];
+            source_address.copy_from_slice(slice);
 
-                println!("length = {length}");
+            let slice = slice::from_raw_parts(ptr.byte_add(12), 2);
+            let ether_type = EtherType::from(u16::from_be_bytes(slice.try_into().unwrap()));
 
-                let ptr = descriptor.buffer_address as *const u8;
+            let ethernet_frame = EthernetPacket {
+                ether_type,
+                destination_address,
+                source_address,
+            };
 
-                let slice = slice::from_raw_parts(ptr, 6);
-                let mut destination_address = [0; 6];
-                destination_address.copy_from_slice(slice);
+            let payload = slice::from_raw_parts(ptr.byte_add(14), (length - 14) as usize);
 
-                let slice = slice::from_raw_parts(ptr.byte_add(6), 6);
-                let mut source_address = [0; 6];
-                source_address.copy_from_slice(slice);
+            println!("payload = {:?}", payload);
 
-                let slice = slice::from_raw_parts(ptr.byte_add(12), 2);
-                let ether_type = EtherType::from(u16::from_be_bytes(slice.try_into().unwrap()));
+            if !sent_arp_reply {
+                let arp_packet = ArpPacket {
+                    hardware_type: u16::from_be_bytes(payload[0..2].try_into().unwrap()), // Ethernet = 1
+                    protocol_type: u16::from_be_bytes(payload[2..4].try_into().unwrap()), // Ipv4 = 0x0800
+                    hardware_length: payload[4], // MAC Address = 6
+                    protocol_length: payload[5], // IPv4 Address = 4
+                    opcode: u16::from_be_bytes(payload[6..8].try_into().unwrap()), // 1 = Request, 2 = Reply
+                    sender_hardware_address: payload[8..14].try_into().unwrap(),
+                    sender_protocol_address: payload[14..18].try_into().unwrap(),
+                    target_hardware_address: payload[18..24].try_into().unwrap(),
+                    target_protocol_address: payload[24..28].try_into().unwrap(),
+                };
 
-                let ethernet_frame = EthernetPacket {
-                    ether_type,
-                    destination_address,
-                    source_address,
+                println!(
+                    "ethertype: {:?}, destination_address: {:?}, payload: {:?}",
+                    ethernet_frame.ether_type, ethernet_frame.destination_address, payload
+                );
+
+                println!("arp_packet = {:?}", arp_packet);
+
+                let reply_arp_packet = ArpPacket {
+                    hardware_type: 1,      // Ethernet = 1
+                    protocol_type: 0x0800, // Ipv4 = 0x0800
+                    hardware_length: 6,    // MAC Address = 6
+                    protocol_length: 4,    // IPv4 Address = 4
+                    opcode: 0x2,           // 1 = Request, 2 = Reply
+                    sender_hardware_address: slice::from_raw_parts(
+                        mac_memory_address as *const u8,
+                        6,
+                    )
+                    .try_into()
+                    .unwrap(),
+                    sender_protocol_address: params::get().ip.address.0,
+                    target_hardware_address: arp_packet.sender_hardware_address,
+                    target_protocol_address: arp_packet.sender_protocol_address,
                 };
 
-                let payload = slice::from_raw_parts(ptr.byte_add(14), (length - 14) as usize);
-
-                println!("payload = {:?}", payload);
-
-                if !sent_arp_reply {
-                    let arp_packet = ArpPacket {
-                        hardware_type: u16::from_be_bytes(payload[0..2].try_into().unwrap()), // Ethernet = 1
-                        protocol_type: u16::from_be_bytes(payload[2..4].try_into().unwrap()), // Ipv4 = 0x0800
-                        hardware_length: payload[4], // MAC Address = 6
-                        protocol_length: payload[5], // IPv4 Address = 4
-                        opcode: u16::from_be_bytes(payload[6..8].try_into().unwrap()), // 1 = Request, 2 = Reply
-                        sender_hardware_address: payload[8..14].try_into().unwrap(),
-                        sender_protocol_address: payload[14..18].try_into().unwrap(),
-                        target_hardware_address: payload[18..24].try_into().unwrap(),
-                        target_protocol_address: payload[24..28].try_into().unwrap(),
-                    };
-
-                    println!(
-                        "ethertype: {:?}, destination_address: {:?}, payload: {:?}",
-                        ethernet_frame.ether_type, ethernet_frame.destination_address, payload
-                    );
-
-                    println!("arp_packet = {:?}", arp_packet);
-
-                    let reply_arp_packet = ArpPacket {
-                        hardware_type: 1,      // Ethernet = 1
-                        protocol_type: 0x0800, // Ipv4 = 0x0800
-                        hardware_length: 6,    // MAC Address = 6
-                        protocol_length: 4,    // IPv4 Address = 4
-                        opcode: 0x2,           // 1 = Request, 2 = Reply
-                        sender_hardware_address: slice::from_raw_parts(
-                            mac_memory_address as *const u8,
-                            6,
-                        )
+                let reply_ethernet_packet = EthernetPacket {
+                    destination_address: arp_packet.sender_hardware_address,
+                    source_address: slice::from_raw_parts(mac_memory_address as *const u8, 6)
                         .try_into()
                         .unwrap(),
-                        sender_protocol_address: [192, 168, 100, 2], // google.com IPv4 address
-                        target_hardware_address: arp_packet.sender_hardware_address,
-                        target_protocol_address: arp_packet.sender_protocol_address,
-                    };
-
-                    let reply_ethernet_packet = EthernetPacket {
-                        destination_address: arp_packet.sender_hardware_address,
-                        source_address: slice::from_raw_parts(mac_memory_address as *const u8, 6)
-                            .try_into()
-                            .unwrap(),
-                        ether_type: EtherType::Arp,
-                    };
-
-                    let transmit_descriptor = &mut TRANSMIT_RING.0[transmit_current];
-
-                    transmit_descriptor.length = 42; // THE ANSWER FOR EVERYTHING IN THE UNIVERSE!
-                    transmit_descriptor.command = 1 /* EOP (End Of Packet) */ | 1 << 1 /* IFCS (Insert Frame Check Sequence) */ | 1 << 3 /* RS (Report Status) */;
-
-                    let buffer_address = transmit_descriptor.buffer_address as *mut u8;
-                    let buffer = slice::from_raw_parts_mut(buffer_address, 42);
-
-                    buffer[0..6].copy_from_slice(&reply_ethernet_packet.destination_address);
-                    buffer[6..12].copy_from_slice(&reply_ethernet_packet.source_address);
-                    buffer[12..14]
-                        .copy_from_slice(&(reply_ethernet_packet.ether_type as u16).to_be_bytes());
-                    buffer[14..16].copy_from_slice(&reply_arp_packet.hardware_type.to_be_bytes());
-                    buffer[16..18].copy_from_slice(&reply_arp_packet.protocol_type.to_be_bytes());
-                    buffer[18] = reply_arp_packet.hardware_length;
-                    buffer[19] = reply_arp_packet.protocol_length;
-                    buffer[20..22].copy_from_slice(&reply_arp_packet.opcode.to_be_bytes());
-                    buffer[22..28].copy_from_slice(&reply_arp_packet.sender_hardware_address);
-                    buffer[28..32].copy_from_slice(&reply_arp_packet.sender_protocol_address);
-                    buffer[32..38].copy_from_slice(&reply_arp_packet.target_hardware_address);
-                    buffer[38..42].copy_from_slice(&reply_arp_packet.target_protocol_address);
-
-                    println!("{:?}", buffer);
-
-                    transmit_current = (transmit_current + 1) % 32;
-
-                    // Write TDT
-                    (mmio_ptr.byte_add(0x3818)).write_volatile(transmit_current as u32);
-
-                    sent_arp_reply = true;
-                }
+                    ether_type: EtherType::Arp,
+                };
 
-                println!("a");
+                let mut transmit_ring = TRANSMIT_RING.lock();
+                let transmit_descriptor = &mut transmit_ring.0[transmit_current];
+
+                transmit_descriptor.length = 42; // THE ANSWER FOR EVERYTHING IN THE UNIVERSE!
+                transmit_descriptor.command = 1 /* EOP (End Of Packet) */ | 1 << 1 /* IFCS (Insert Frame Check Sequence) */ | 1 << 3 /* RS (Report Status) */;
+
+                let buffer_address =
+                    layout::physical_to_virtual(transmit_descriptor.buffer_address as usize)
+                        as *mut u8;
+                let buffer = slice::from_raw_parts_mut(buffer_address, 42);
+
+                buffer[0..6].copy_from_slice(&reply_ethernet_packet.destination_address);
+                buffer[6..12].copy_from_slice(&reply_ethernet_packet.source_address);
+                buffer[12..14]
+                    .copy_from_slice(&(reply_ethernet_packet.ether_type as u16).to_be_bytes());
+                buffer[14..16].copy_from_slice(&reply_arp_packet.hardware_type.to_be_bytes());
+                buffer[16..18].copy_from_slice(&reply_arp_packet.protocol_type.to_be_bytes());
+                buffer[18] = reply_arp_packet.hardware_length;
+                buffer[19] = reply_arp_packet.protocol_length;
+                buffer[20..22].copy_from_slice(&reply_arp_packet.opcode.to_be_bytes());
+                buffer[22..28].copy_from_slice(&reply_arp_packet.sender_hardware_address);
+                buffer[28..32].copy_from_slice(&reply_arp_packet.sender_protocol_address);
+                buffer[32..38].copy_from_slice(&reply_arp_packet.target_hardware_address);
+                buffer[38..42].copy_from_slice(&reply_arp_packet.target_protocol_address);
+
+                transmit_current = (transmit_current + 1) % 32;
+
+                // Write TDT
+                (mmio_ptr.byte_add(0x3818)).write_volatile(transmit_current as u32);
+
+                sent_arp_reply = true;
+            }
+
+            {
+                let mut receive_ring = RECEIVE_RING.lock();
+                let descriptor = &mut receive_ring.0[receive_current];
 
                 descriptor.length = 0;
                 descriptor.status = 0;
+            }
 
-                // Write RDT
-                (mmio_ptr.byte_add(0x2818)).write_volatile(receive_current as u32);
+            // Write RDT
+            (mmio_ptr.byte_add(0x2818)).write_volatile(receive_current as u32);
 
-                receive_current = (receive_current + 1) % 32;
-            }
+            receive_current = (receive_current + 1) % 32;
         }
     }
 }
@@ -378,7 +446,7 @@ struct RingBuffers([[u8; 2048]; 32]);
 #[repr(align(16))]
 struct ReceiveRing([NetworkReceiveDescriptor; 32]);
 
-static mut RECEIVE_RING: ReceiveRing = ReceiveRing(
+static RECEIVE_RING: IrqSpinLock<ReceiveRing> = IrqSpinLock::new(ReceiveRing(
     [NetworkReceiveDescriptor {
         buffer_address: 0,
         length: 0,
@@ -387,14 +455,14 @@ static mut RECEIVE_RING: ReceiveRing = ReceiveRing(
         special: 0,
         status: 0,
     }; 32],
-);
+));
 
 static RECEIVE_RING_BUFFERS: RingBuffers = RingBuffers([[0u8; 2048]; 32]);
 
 #[repr(align(16))]
 struct TransmitRing([NetworkTransmitDescriptor; 32]);
 
-static mut TRANSMIT_RING: TransmitRing = TransmitRing(
+static TRANSMIT_RING: IrqSpinLock<TransmitRing> = IrqSpinLock::new(TransmitRing(
     [NetworkTransmitDescriptor {
         buffer_address: 0,
         length: 0,
@@ -404,7 +472,7 @@ static mut TRANSMIT_RING: TransmitRing = TransmitRing(
         checksum_start: 0,
         special: 0,
     }; 32],
-);
+));
 
 #[repr(C)]
 #[derive(Copy, Clone)]
@@ -2,12 +2,12 @@ use core::fmt;
 use core::fmt::Arguments;
 use core::fmt::Write;
 
-use crate::arch::interrupts;
 use crate::arch::serial;
+use crate::sync::irq_spin_lock::IrqSpinLock;
 
 pub struct Serial;
 
-pub static mut SERIAL: Serial = Serial;
+pub static SERIAL: IrqSpinLock<Serial> = IrqSpinLock::new(Serial);
 
 impl fmt::Write for Serial {
     fn write_str(&mut self, str: &str) -> fmt::Result {
@@ -19,16 +19,13 @@ impl fmt::Write for Serial {
 
 #[doc(hidden)]
 pub fn _print(args: Arguments) {
-    interrupts::without_interrupts(|| unsafe {
-        #[allow(static_mut_refs)]
-        SERIAL.write_fmt(args).unwrap();
-    });
+    SERIAL.lock().write_fmt(args).unwrap();
 }
 
 #[macro_export]
 macro_rules! serial_print {
     ($($arg:tt)*) => {
-        $crate::serial::_print(format_args!($($arg)*))
+        $crate::device::serial::_print(format_args!($($arg)*))
     };
 }
 
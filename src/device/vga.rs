@@ -1,15 +1,15 @@
 use core::fmt;
 use core::ptr;
 
-use spin::Mutex;
-
-use crate::serial::SERIAL;
+use crate::device::serial::SERIAL;
 use crate::{
-    arch::{interrupts, io},
-    memory::util,
+    arch::io,
+    boot::params,
+    memory::{layout, util},
+    sync::irq_spin_lock::IrqSpinLock,
 };
 
-const VGA_BUFFER: *mut u8 = 0xb8000 as *mut u8;
+const VGA_BUFFER: *mut u8 = layout::physical_to_virtual(0xb8000) as *mut u8;
 #[allow(dead_code)]
 const VGA_WIDTH: u16 = 80;
 const VGA_HEIGHT: u16 = 25;
@@ -18,11 +18,19 @@ const VGA_DATA_REGISTER_PORT: u16 = 0x3D5;
 const COLOR: u8 = 0x0F;
 const BLANK_CHARACTER: u16 = 0x20 | ((COLOR as u16) << 8);
 
-pub static VGA_SCREEN: Mutex<VGAScreen> = Mutex::new(VGAScreen { x: 0, y: 0 });
+pub static VGA_SCREEN: IrqSpinLock<VGAScreen> = IrqSpinLock::new(VGAScreen {
+    x: 0,
+    y: 0,
+    color: COLOR,
+    available: true,
+});
 
 pub struct VGAScreen {
     x: u16,
     y: u16,
+    color: u8,
+    /// Cleared when there is no text mode buffer at 0xB8000, e.g. on UEFI machines.
+    available: bool,
 }
 
 impl VGAScreen {
@@ -33,6 +41,10 @@ impl VGAScreen {
     }
 
     pub fn write_char(&mut self, character: char) {
+        if !self.available {
+            return;
+        }
+
         let character_byte = normalize_to_cp437(character);
 
         match character_byte {
@@ -56,7 +68,7 @@ impl VGAScreen {
 
                 unsafe {
                     *VGA_BUFFER.add(offset) = character_byte;
-                    *VGA_BUFFER.add(offset + 1) = COLOR;
+                    *VGA_BUFFER.add(offset + 1) = self.color;
                 }
 
                 self.x += 1;
@@ -72,10 +84,28 @@ impl VGAScreen {
         self.update_hardware_cursor();
     }
 
+    /// Attribute byte (background in the high nibble, foreground in the low one) of the next characters.
+    pub fn set_color(&mut self, color: u8) {
+        self.color = color;
+    }
+
+    /// Stops all output, the text buffer may be backed by nothing or by another device.
+    pub fn disable(&mut self) {
+        self.available = false;
+    }
+
     pub fn clear_screen(&mut self) {
+        if !self.available {
+            return;
+        }
+
         for line in 0..25 {
             unsafe {
-                util::memsetw(VGA_BUFFER.add(line * 80) as *mut u16, BLANK_CHARACTER, 80);
+                util::memsetw(
+                    VGA_BUFFER.add(line * 80 * 2) as *mut u16,
+                    BLANK_CHARACTER,
+                    80,
+                );
             }
         }
 
@@ -135,12 +165,12 @@ impl VGAScreen {
 
 #[macro_export]
 macro_rules! print {
-    ($($arg:tt)*) => ($crate::screen::vga::_print(format_args!($($arg)*)));
+    ($($arg:tt)*) => ($crate::device::vga::_print(format_args!($($arg)*)));
 }
 
 #[macro_export]
 macro_rules! println {
-    () => ($crate::print("\n"));
+    () => ($crate::print!("\n"));
     ($($arg:tt)*) => ($crate::print!("{}\n", format_args!($($arg)*)));
 }
 
@@ -155,13 +185,15 @@ impl fmt::Write for VGAScreen {
 pub fn _print(args: fmt::Arguments) {
     use core::fmt::Write;
 
-    interrupts::without_interrupts(|| {
-        unsafe {
-            #[allow(static_mut_refs)]
-            SERIAL.write_fmt(args).unwrap();
-        }
+    let console = params::get().console;
+
+    if console.serial {
+        SERIAL.lock().write_fmt(args).unwrap();
+    }
+
+    if console.vga {
         VGA_SCREEN.lock().write_fmt(args).unwrap();
-    });
+    }
 }
 
 fn normalize_to_cp437(character: char) -> u8 {
@@ -1,31 +1,315 @@
 #![no_std]
 #![cfg_attr(test, no_main)]
+#![feature(alloc_error_handler)]
 
-use core::panic::{self, PanicInfo};
+extern crate alloc;
+
+use core::{alloc::Layout, panic::PanicInfo};
+
+#[macro_use]
+pub mod device;
+pub mod acpi;
+pub mod arch;
+pub mod boot;
+pub mod debug;
+pub mod memory;
+pub mod network;
+pub mod sync;
+pub mod syscall;
+pub mod task;
+pub mod thread;
+pub mod time;
+
+use acpi::AcpiTables;
+use arch::{
+    cpu::{self, Features},
+    gdt, idt, interrupts, irq, percpu, smp,
+};
+use boot::{
+    multiboot2::BootInformation,
+    params::{self, LogLevel},
+};
+use device::pci;
+use device::vga::VGA_SCREEN;
+use memory::frame_allocator::{self, FRAME_SIZE};
 
 #[no_mangle]
-pub extern "C" fn kernel_enter() {
-    let message = b"Hello WorlZ!";
-    let color_byte = 0x1f;
+pub extern "C" fn kernel_enter(multiboot_information_address: usize) {
+    gdt::install(smp::BSP_INDEX);
+    percpu::init(smp::BSP_INDEX);
+    idt::init_idt();
+    irq::init();
+    interrupts::enable();
+
+    let boot_information_address =
+        memory::layout::physical_to_virtual(multiboot_information_address);
+
+    let boot_information = match unsafe { BootInformation::load(boot_information_address) } {
+        Ok(boot_information) => boot_information,
+        Err(error) => panic!("Invalid Multiboot2 boot information: {:?}", error),
+    };
+
+    if boot_information.has_vga_text_mode() {
+        VGA_SCREEN.lock().clear_screen();
+    } else {
+        VGA_SCREEN.lock().disable();
+    }
+
+    let parameters = params::init(boot_information.command_line().unwrap_or(""));
+
+    println!(
+        "Booted by {} ({} bytes of boot information at {:#x})",
+        boot_information
+            .boot_loader_name()
+            .unwrap_or("unknown boot loader"),
+        boot_information.total_size(),
+        boot_information.start_address()
+    );
+
+    if parameters.log_level >= LogLevel::Debug {
+        print_boot_information(&boot_information);
+    }
+
+    match frame_allocator::init(&boot_information) {
+        Ok(statistics) => {
+            println!(
+                "Memory: {} MiB free, {} MiB reserved",
+                (statistics.free() * FRAME_SIZE as usize) >> 20,
+                (statistics.reserved * FRAME_SIZE as usize) >> 20
+            );
+
+            if statistics.ignored > 0 {
+                println!(
+                    "warning: {} MiB of memory above 3 GiB is not used",
+                    (statistics.ignored * FRAME_SIZE as usize) >> 20
+                );
+            }
+        }
+        Err(error) => println!("warning: no physical memory allocator: {:?}", error),
+    }
+
+    let enabled_features = cpu::enable_features();
+
+    print_cpu_summary(enabled_features);
+
+    match arch::paging::init() {
+        Ok(()) => {
+            if let Err(error) = memory::heap::init() {
+                println!("warning: failed to map the kernel heap: {:?}", error);
+            }
+        }
+        Err(error) => println!("warning: page tables cannot be changed: {:?}", error),
+    }
+
+    if !arch::syscall::init() {
+        println!("warning: SYSCALL is not supported, user mode cannot make system calls");
+    }
+
+    let acpi_tables = match acpi::init(&boot_information) {
+        Ok(tables) => {
+            if parameters.log_level >= LogLevel::Debug {
+                print_acpi_tables(tables);
+            }
+
+            use_apic(tables);
 
-    let mut hello_colored = [color_byte; 24];
+            Some(tables)
+        }
+        Err(error) => {
+            println!(
+                "warning: no usable ACPI tables ({:?}), staying on the 8259 PICs",
+                error
+            );
 
-    for (i, char_byte) in message.into_iter().enumerate() {
-        hello_colored[i * 2] = *char_byte;
+            None
+        }
+    };
+
+    let hpet = acpi_tables.and_then(|tables| tables.hpet().ok());
+    let clock_source = time::init(hpet.as_ref());
+
+    let century_register = acpi_tables
+        .and_then(|tables| tables.fadt().ok())
+        .map_or(0, |fadt| fadt.century_register);
+    let date_time = time::init_wall_clock(century_register);
+
+    if parameters.log_level >= LogLevel::Debug {
+        println!(
+            "Clock source: {:?}, TSC at {} kHz",
+            clock_source,
+            time::tsc_frequency() / 1000
+        );
+        println!("Wall clock: {}", date_time);
     }
 
-    let buffer_ptr = (0xb8000 + 1988) as *mut _;
+    smp::init_bsp();
+
+    if let Some(madt) = acpi_tables.and_then(|tables| tables.madt().ok()) {
+        let apic_ids = madt
+            .processors()
+            .filter(|processor| processor.enabled)
+            .map(|processor| processor.apic_id);
 
-    unsafe {
-        *buffer_ptr = hello_colored;
+        smp::start_application_processors(apic_ids);
     }
 
-    loop {}
+    if parameters.log_level >= LogLevel::Debug {
+        for cpu in smp::cpus() {
+            println!(
+                "CPU {} (APIC ID {}) {}",
+                cpu.index,
+                cpu.apic_id,
+                if cpu.online { "online" } else { "offline" }
+            );
+        }
+    }
+
+    match thread::init() {
+        Ok(()) => {
+            if let Err(error) = task::executor::start() {
+                println!("warning: failed to start the task executor: {:?}", error);
+            }
+        }
+        Err(error) => println!("warning: failed to start the scheduler: {:?}", error),
+    }
+
+    pci::visit_buses();
+
+    // Leave the CPU to the threads started so far.
+    thread::exit();
+}
+
+fn print_boot_information(boot_information: &BootInformation) {
+    println!(
+        "Command line: {}",
+        boot_information.command_line().unwrap_or("")
+    );
+
+    if let Some(memory_map) = boot_information.memory_map() {
+        println!("Memory map:");
+
+        for area in memory_map.areas() {
+            println!(
+                "  {:#014x} - {:#014x} {:?}",
+                area.base_address,
+                area.end_address(),
+                area.area_type
+            );
+        }
+    }
+
+    if let Some(system_table) = boot_information.efi_system_table() {
+        println!("EFI system table at {:#x}", system_table);
+    }
+
+    for module in boot_information.modules() {
+        println!(
+            "Module {:#x} - {:#x}: {}",
+            module.start_address, module.end_address, module.command_line
+        );
+    }
+}
+
+fn print_cpu_summary(enabled_features: Features) {
+    let info = cpu::info();
+
+    println!(
+        "CPU: {} family {:#x} model {:#x} stepping {} ({})",
+        info.vendor_id(),
+        info.family,
+        info.model,
+        info.stepping,
+        info.brand()
+    );
+
+    print!("  Features:");
+
+    for feature in info.features.iter() {
+        print!(" {}", feature.name());
+    }
+
+    println!();
+
+    print!("  Enabled:");
+
+    for feature in enabled_features.iter() {
+        print!(" {}", feature.name());
+    }
+
+    println!();
+}
+
+fn print_acpi_tables(tables: &AcpiTables) {
+    print!("ACPI {} tables:", tables.rsdp.revision);
+
+    for table in tables.tables() {
+        print!(" {}", table.header.signature);
+    }
+
+    println!();
+
+    if let Ok(madt) = tables.madt() {
+        for processor in madt.processors() {
+            println!(
+                "  CPU {} (APIC ID {}){}",
+                processor.processor_uid,
+                processor.apic_id,
+                if processor.enabled { "" } else { ", disabled" }
+            );
+        }
+
+        for io_apic in madt.io_apics() {
+            println!(
+                "  I/O APIC {} at {:#x}, GSI base {}",
+                io_apic.id, io_apic.address, io_apic.gsi_base
+            );
+        }
+
+        for interrupt_source_override in madt.interrupt_source_overrides() {
+            println!(
+                "  IRQ {} -> GSI {} ({:?}, {:?})",
+                interrupt_source_override.source,
+                interrupt_source_override.gsi,
+                interrupt_source_override.polarity,
+                interrupt_source_override.trigger_mode
+            );
+        }
+    }
+}
+
+fn use_apic(tables: &AcpiTables) {
+    let madt = match tables.madt() {
+        Ok(madt) => madt,
+        Err(error) => {
+            println!("warning: no MADT ({:?}), staying on the 8259 PICs", error);
+            return;
+        }
+    };
+
+    if let Err(error) = irq::use_apic(madt.io_apics(), madt.interrupt_source_overrides()) {
+        println!(
+            "warning: failed to route interrupts through the I/O APIC: {:?}",
+            error
+        );
+    }
 }
 
 #[panic_handler]
-fn panic(_info: &PanicInfo) -> ! {
-    loop {}
+fn panic(info: &PanicInfo) -> ! {
+    arch::crash::panic(info)
+}
+
+#[alloc_error_handler]
+fn allocation_error(layout: Layout) -> ! {
+    let heap = memory::heap::statistics();
+
+    panic!(
+        "out of memory allocating {} bytes aligned to {} ({} of {} heap bytes in use)",
+        layout.size(),
+        layout.align(),
+        heap.used,
+        heap.size
+    );
 }
 
 // #![feature(custom_test_frameworks)]
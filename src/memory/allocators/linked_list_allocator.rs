@@ -0,0 +1,155 @@
+//! First-fit allocator over a free list kept in address order, so a freed block merges with the
+//! free blocks on either side of it.
+
+use core::{
+    alloc::Layout,
+    mem,
+    ptr::{self, NonNull},
+};
+
+/// Written at the start of every free block.
+struct FreeBlock {
+    size: usize,
+    next: *mut FreeBlock,
+}
+
+/// Blocks are multiples of this size and aligned to it, so whatever is left around an allocation
+/// is either nothing or big enough to hold a `FreeBlock`.
+pub const BLOCK_SIZE: usize = 16;
+
+const _: () = assert!(mem::size_of::<FreeBlock>() <= BLOCK_SIZE);
+
+pub struct LinkedListAllocator {
+    head: *mut FreeBlock,
+    free: usize,
+}
+
+// The blocks are only reached through the allocator.
+unsafe impl Send for LinkedListAllocator {}
+
+impl LinkedListAllocator {
+    pub const fn new() -> Self {
+        Self {
+            head: ptr::null_mut(),
+            free: 0,
+        }
+    }
+
+    /// Size and alignment of the block that holds an allocation of `layout`.
+    pub fn block_layout(layout: Layout) -> (usize, usize) {
+        (
+            layout.size().max(1).next_multiple_of(BLOCK_SIZE),
+            layout.align().max(BLOCK_SIZE),
+        )
+    }
+
+    /// Bytes in free blocks.
+    pub fn free(&self) -> usize {
+        self.free
+    }
+
+    /// Hands `[start, start + size)` over to the allocator. Bytes outside the block alignment are
+    /// left out.
+    ///
+    /// # Safety
+    ///
+    /// The memory must be writable, unused, and not already part of the allocator.
+    pub unsafe fn add_region(&mut self, start: usize, size: usize) {
+        let aligned_start = start.next_multiple_of(BLOCK_SIZE);
+        let end = (start + size) / BLOCK_SIZE * BLOCK_SIZE;
+
+        if end > aligned_start {
+            self.insert(aligned_start, end - aligned_start);
+        }
+    }
+
+    pub fn allocate(&mut self, layout: Layout) -> Option<NonNull<u8>> {
+        let (size, align) = Self::block_layout(layout);
+        let mut previous: *mut FreeBlock = ptr::null_mut();
+        let mut current = self.head;
+
+        while !current.is_null() {
+            let (block_start, block_size, next) =
+                unsafe { (current as usize, (*current).size, (*current).next) };
+            let block_end = block_start + block_size;
+            let start = block_start.next_multiple_of(align);
+
+            if start.checked_add(size).is_some_and(|end| end <= block_end) {
+                if previous.is_null() {
+                    self.head = next;
+                } else {
+                    unsafe { (*previous).next = next };
+                }
+
+                self.free -= block_size;
+
+                // What is left in front of and behind the allocation goes back to the list.
+                unsafe {
+                    self.insert(block_start, start - block_start);
+                    self.insert(start + size, block_end - start - size);
+                }
+
+                return NonNull::new(start as *mut u8);
+            }
+
+            previous = current;
+            current = next;
+        }
+
+        None
+    }
+
+    /// # Safety
+    ///
+    /// `pointer` must come from `allocate` with the same `layout`.
+    pub unsafe fn deallocate(&mut self, pointer: NonNull<u8>, layout: Layout) {
+        let (size, _) = Self::block_layout(layout);
+
+        self.insert(pointer.as_ptr() as usize, size);
+    }
+
+    /// Links the block in at its place in the list, merged with the free blocks it touches.
+    unsafe fn insert(&mut self, start: usize, size: usize) {
+        if size == 0 {
+            return;
+        }
+
+        let mut previous: *mut FreeBlock = ptr::null_mut();
+        let mut next = self.head;
+
+        while !next.is_null() && (next as usize) < start {
+            previous = next;
+            next = (*next).next;
+        }
+
+        self.free += size;
+
+        let mut size = size;
+
+        if !next.is_null() && start + size == next as usize {
+            size += (*next).size;
+            next = (*next).next;
+        }
+
+        if !previous.is_null() && previous as usize + (*previous).size == start {
+            (*previous).size += size;
+            (*previous).next = next;
+        } else {
+            let block = start as *mut FreeBlock;
+
+            block.write(FreeBlock { size, next });
+
+            if previous.is_null() {
+                self.head = block;
+            } else {
+                (*previous).next = block;
+            }
+        }
+    }
+}
+
+impl Default for LinkedListAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
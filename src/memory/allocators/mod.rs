@@ -0,0 +1,2 @@
+pub mod linked_list_allocator;
+pub mod slab_allocator;
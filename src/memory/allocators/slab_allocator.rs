@@ -0,0 +1,329 @@
+//! Object caches: fixed-size objects carved out of slabs of physically contiguous frames, reached
+//! through the direct map. A slab is aligned to its size and starts with its header, so the slab of
+//! an object is found by rounding its address down.
+
+use core::{
+    iter, mem,
+    ptr::{self, NonNull},
+    sync::atomic::{AtomicBool, AtomicPtr, Ordering},
+};
+
+use crate::{
+    memory::{
+        frame_allocator::{self, Frame, FRAME_SIZE},
+        layout,
+    },
+    sync::irq_spin_lock::IrqSpinLock,
+};
+
+/// Slabs grow until they hold this many objects, or reach `MAX_SLAB_FRAMES`.
+const MIN_OBJECTS_PER_SLAB: usize = 8;
+const MAX_SLAB_FRAMES: usize = 16;
+
+/// At the start of every slab.
+struct Slab {
+    cache: *const ObjectCache,
+    previous: *mut Slab,
+    next: *mut Slab,
+    free: *mut FreeObject,
+    in_use: usize,
+}
+
+/// Written over a free object.
+struct FreeObject {
+    next: *mut FreeObject,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CacheStatistics {
+    pub slabs: usize,
+    pub objects_in_use: usize,
+    pub allocations: u64,
+    pub frees: u64,
+}
+
+struct Slabs {
+    /// Slabs with at least one free object. Full slabs are only reached through their objects.
+    partial: *mut Slab,
+    statistics: CacheStatistics,
+}
+
+// Slabs are only reached with the cache locked.
+unsafe impl Send for Slabs {}
+
+pub struct ObjectCache {
+    name: &'static str,
+    object_size: usize,
+    align: usize,
+    /// Offset of the first object, past the slab header.
+    first_object: usize,
+    slab_frames: usize,
+    constructor: Option<fn(NonNull<u8>)>,
+    slabs: IrqSpinLock<Slabs>,
+    registered: AtomicBool,
+    next: AtomicPtr<ObjectCache>,
+}
+
+/// Every cache that has allocated, most recent first.
+static CACHES: AtomicPtr<ObjectCache> = AtomicPtr::new(ptr::null_mut());
+
+impl ObjectCache {
+    /// A cache of `object_size` byte objects aligned to `align`, a power of two. `constructor`
+    /// runs on every object before it is handed out.
+    pub const fn new(
+        name: &'static str,
+        object_size: usize,
+        align: usize,
+        constructor: Option<fn(NonNull<u8>)>,
+    ) -> Self {
+        assert!(align.is_power_of_two());
+
+        let align = if align < mem::align_of::<FreeObject>() {
+            mem::align_of::<FreeObject>()
+        } else {
+            align
+        };
+
+        let object_size = if object_size < mem::size_of::<FreeObject>() {
+            mem::size_of::<FreeObject>()
+        } else {
+            object_size
+        }
+        .next_multiple_of(align);
+
+        let first_object = mem::size_of::<Slab>().next_multiple_of(align);
+        let mut slab_frames = 1;
+
+        while slab_frames < MAX_SLAB_FRAMES
+            && (slab_frames * FRAME_SIZE as usize - first_object) / object_size
+                < MIN_OBJECTS_PER_SLAB
+        {
+            slab_frames *= 2;
+        }
+
+        assert!(
+            first_object + object_size <= slab_frames * FRAME_SIZE as usize,
+            "object too large for a slab"
+        );
+
+        Self {
+            name,
+            object_size,
+            align,
+            first_object,
+            slab_frames,
+            constructor,
+            slabs: IrqSpinLock::new(Slabs {
+                partial: ptr::null_mut(),
+                statistics: CacheStatistics {
+                    slabs: 0,
+                    objects_in_use: 0,
+                    allocations: 0,
+                    frees: 0,
+                },
+            }),
+            registered: AtomicBool::new(false),
+            next: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Size of the objects, rounded up to their alignment.
+    pub fn object_size(&self) -> usize {
+        self.object_size
+    }
+
+    pub fn align(&self) -> usize {
+        self.align
+    }
+
+    pub fn objects_per_slab(&self) -> usize {
+        (self.slab_size() - self.first_object) / self.object_size
+    }
+
+    pub fn statistics(&self) -> CacheStatistics {
+        self.slabs.lock().statistics
+    }
+
+    /// `None` when no frames are left for a new slab.
+    pub fn allocate(&'static self) -> Option<NonNull<u8>> {
+        self.register();
+
+        let object = {
+            let mut slabs = self.slabs.lock();
+
+            if slabs.partial.is_null() {
+                let slab = self.create_slab()?;
+
+                unsafe { push(&mut slabs.partial, slab) };
+
+                slabs.statistics.slabs += 1;
+            }
+
+            let slab = slabs.partial;
+
+            unsafe {
+                let object = (*slab).free;
+
+                (*slab).free = (*object).next;
+                (*slab).in_use += 1;
+
+                if (*slab).free.is_null() {
+                    remove(&mut slabs.partial, slab);
+                }
+
+                slabs.statistics.objects_in_use += 1;
+                slabs.statistics.allocations += 1;
+
+                NonNull::new_unchecked(object as *mut u8)
+            }
+        };
+
+        if let Some(constructor) = self.constructor {
+            constructor(object);
+        }
+
+        Some(object)
+    }
+
+    /// Gives an object back. A slab left empty goes back to the frame allocator, unless it is the
+    /// only one with room left, so that allocating and freeing a single object does not allocate a
+    /// slab every time.
+    ///
+    /// # Safety
+    ///
+    /// `object` must come from `allocate` on this cache, and not be used afterwards.
+    pub unsafe fn free(&self, object: NonNull<u8>) {
+        let slab = (object.as_ptr() as usize & !(self.slab_size() - 1)) as *mut Slab;
+
+        assert!(
+            ptr::eq((*slab).cache, self),
+            "object {:p} freed to the wrong cache, {}",
+            object,
+            self.name
+        );
+
+        let mut slabs = self.slabs.lock();
+        let object = object.as_ptr() as *mut FreeObject;
+        let was_full = (*slab).free.is_null();
+
+        (*object).next = (*slab).free;
+        (*slab).free = object;
+        (*slab).in_use -= 1;
+
+        slabs.statistics.objects_in_use -= 1;
+        slabs.statistics.frees += 1;
+
+        if was_full {
+            push(&mut slabs.partial, slab);
+        }
+
+        if (*slab).in_use == 0 && !(slabs.partial == slab && (*slab).next.is_null()) {
+            remove(&mut slabs.partial, slab);
+
+            slabs.statistics.slabs -= 1;
+
+            frame_allocator::free_contiguous(
+                Frame::containing(layout::virtual_to_physical(slab as usize) as u64),
+                self.slab_frames,
+            );
+        }
+    }
+
+    fn slab_size(&self) -> usize {
+        self.slab_frames * FRAME_SIZE as usize
+    }
+
+    fn create_slab(&self) -> Option<*mut Slab> {
+        // Aligned physically, and so in the direct map, to the slab size.
+        let frame = frame_allocator::allocate_contiguous(self.slab_frames, self.slab_frames)?;
+        let start = layout::physical_to_virtual(frame.start_address() as usize);
+        let slab = start as *mut Slab;
+
+        let mut free: *mut FreeObject = ptr::null_mut();
+
+        for index in (0..self.objects_per_slab()).rev() {
+            let object = (start + self.first_object + index * self.object_size) as *mut FreeObject;
+
+            unsafe { object.write(FreeObject { next: free }) };
+
+            free = object;
+        }
+
+        unsafe {
+            slab.write(Slab {
+                cache: self,
+                previous: ptr::null_mut(),
+                next: ptr::null_mut(),
+                free,
+                in_use: 0,
+            })
+        };
+
+        Some(slab)
+    }
+
+    fn register(&'static self) {
+        if self.registered.swap(true, Ordering::AcqRel) {
+            return;
+        }
+
+        let mut head = CACHES.load(Ordering::Acquire);
+
+        loop {
+            self.next.store(head, Ordering::Relaxed);
+
+            match CACHES.compare_exchange_weak(
+                head,
+                self as *const ObjectCache as *mut ObjectCache,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => break,
+                Err(current) => head = current,
+            }
+        }
+    }
+}
+
+/// Caches that have allocated at least once.
+pub fn caches() -> impl Iterator<Item = &'static ObjectCache> {
+    let mut current = CACHES.load(Ordering::Acquire);
+
+    iter::from_fn(move || {
+        let cache = unsafe { current.as_ref()? };
+
+        current = cache.next.load(Ordering::Acquire);
+
+        Some(cache)
+    })
+}
+
+unsafe fn push(list: &mut *mut Slab, slab: *mut Slab) {
+    (*slab).previous = ptr::null_mut();
+    (*slab).next = *list;
+
+    if !list.is_null() {
+        (**list).previous = slab;
+    }
+
+    *list = slab;
+}
+
+unsafe fn remove(list: &mut *mut Slab, slab: *mut Slab) {
+    if (*slab).previous.is_null() {
+        *list = (*slab).next;
+    } else {
+        (*(*slab).previous).next = (*slab).next;
+    }
+
+    if !(*slab).next.is_null() {
+        (*(*slab).next).previous = (*slab).previous;
+    }
+
+    (*slab).previous = ptr::null_mut();
+    (*slab).next = ptr::null_mut();
+}
@@ -0,0 +1,284 @@
+//! Physical memory, handed out in 4 KiB frames. A bitmap keeps one bit per frame of the cached part
+//! of the direct map (see layout.rs), set while the frame is free. Frames that are not RAM are
+//! never set.
+
+use core::fmt;
+
+use crate::{
+    boot::multiboot2::{BootInformation, MemoryAreaType},
+    memory::layout::{self, UNCACHED_PHYSICAL_MEMORY_START},
+    sync::irq_spin_lock::IrqSpinLock,
+};
+
+pub const FRAME_SIZE: u64 = 4096;
+
+/// Only memory the direct map caches is managed. RAM in its uncached last GiB would otherwise end up
+/// mapped both uncached there and write-back wherever the frame is mapped again.
+const MAX_FRAMES: usize = UNCACHED_PHYSICAL_MEMORY_START / FRAME_SIZE as usize;
+const BITMAP_WORDS: usize = MAX_FRAMES / u64::BITS as usize;
+
+/// The real mode IVT, the BIOS data area, the EBDA, the SMP trampoline (0x8000-0x9FFF), the VGA
+/// buffer and the BIOS ROM all live below 1 MiB.
+const LOW_MEMORY_END: u64 = 0x10_0000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Frame(u64);
+
+impl Frame {
+    pub const fn containing(physical_address: u64) -> Self {
+        Self(physical_address / FRAME_SIZE)
+    }
+
+    pub const fn from_number(number: u64) -> Self {
+        Self(number)
+    }
+
+    pub const fn number(&self) -> u64 {
+        self.0
+    }
+
+    pub const fn start_address(&self) -> u64 {
+        self.0 * FRAME_SIZE
+    }
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(formatter, "{:#x}", self.start_address())
+    }
+}
+
+#[derive(Debug)]
+pub enum FrameAllocatorError {
+    NoMemoryMap,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FrameStatistics {
+    /// Frames of RAM in the memory map, below the uncached part of the direct map.
+    pub total: usize,
+    /// Frames taken at boot: the kernel image, boot modules and low memory.
+    pub reserved: usize,
+    pub allocated: usize,
+    /// Available RAM from the uncached part of the direct map on, which is not used.
+    pub ignored: usize,
+}
+
+impl FrameStatistics {
+    pub fn free(&self) -> usize {
+        self.total - self.reserved - self.allocated
+    }
+
+    pub fn used(&self) -> usize {
+        self.reserved + self.allocated
+    }
+}
+
+struct FrameAllocator {
+    bitmap: [u64; BITMAP_WORDS],
+    /// Where the search for a single frame starts, right after the last one allocated.
+    next: usize,
+    statistics: FrameStatistics,
+}
+
+impl FrameAllocator {
+    fn is_used(&self, frame: usize) -> bool {
+        self.bitmap[frame / 64] & (1 << (frame % 64)) == 0
+    }
+
+    fn set_used(&mut self, frame: usize, used: bool) {
+        if used {
+            self.bitmap[frame / 64] &= !(1 << (frame % 64));
+        } else {
+            self.bitmap[frame / 64] |= 1 << (frame % 64);
+        }
+    }
+
+    /// Frames fully inside `[start, end)`, clamped to the managed range.
+    fn frames_within(start: u64, end: u64) -> core::ops::Range<usize> {
+        let first = start.div_ceil(FRAME_SIZE).min(MAX_FRAMES as u64);
+        let last = (end / FRAME_SIZE).min(MAX_FRAMES as u64);
+
+        first as usize..last.max(first) as usize
+    }
+
+    /// Frames touching `[start, end)`, clamped to the managed range.
+    fn frames_touching(start: u64, end: u64) -> core::ops::Range<usize> {
+        let first = (start / FRAME_SIZE).min(MAX_FRAMES as u64);
+        let last = end.div_ceil(FRAME_SIZE).min(MAX_FRAMES as u64);
+
+        first as usize..last.max(first) as usize
+    }
+
+    fn add_available(&mut self, start: u64, end: u64) {
+        for frame in Self::frames_within(start, end) {
+            if self.is_used(frame) {
+                self.set_used(frame, false);
+                self.statistics.total += 1;
+            }
+        }
+
+        let managed_end = MAX_FRAMES as u64 * FRAME_SIZE;
+
+        if end > managed_end {
+            self.statistics.ignored += ((end - start.max(managed_end)) / FRAME_SIZE) as usize;
+        }
+    }
+
+    fn reserve(&mut self, start: u64, end: u64) {
+        for frame in Self::frames_touching(start, end) {
+            if !self.is_used(frame) {
+                self.set_used(frame, true);
+                self.statistics.reserved += 1;
+            }
+        }
+    }
+
+    fn allocate(&mut self) -> Option<Frame> {
+        let start_word = self.next / 64;
+
+        for offset in 0..BITMAP_WORDS {
+            let word_index = (start_word + offset) % BITMAP_WORDS;
+            let word = self.bitmap[word_index];
+
+            if word == 0 {
+                continue;
+            }
+
+            let frame = word_index * 64 + word.trailing_zeros() as usize;
+
+            self.set_used(frame, true);
+            self.statistics.allocated += 1;
+            self.next = frame + 1;
+
+            return Some(Frame(frame as u64));
+        }
+
+        None
+    }
+
+    fn allocate_contiguous(&mut self, count: usize, alignment: usize) -> Option<Frame> {
+        let mut start = 0;
+
+        while start + count <= MAX_FRAMES {
+            // Skip whole words in use.
+            if start % 64 == 0 && self.bitmap[start / 64] == 0 {
+                start = (start + 64).next_multiple_of(alignment);
+                continue;
+            }
+
+            match (start..start + count).find(|&frame| self.is_used(frame)) {
+                Some(used) => start = (used + 1).next_multiple_of(alignment),
+                None => {
+                    for frame in start..start + count {
+                        self.set_used(frame, true);
+                    }
+
+                    self.statistics.allocated += count;
+
+                    return Some(Frame(start as u64));
+                }
+            }
+        }
+
+        None
+    }
+
+    fn free(&mut self, first: Frame, count: usize) {
+        for frame in first.0 as usize..first.0 as usize + count {
+            assert!(
+                frame < MAX_FRAMES && self.is_used(frame),
+                "freeing frame {:#x}, which is not allocated",
+                frame as u64 * FRAME_SIZE
+            );
+
+            self.set_used(frame, false);
+        }
+
+        self.statistics.allocated -= count;
+    }
+}
+
+static FRAME_ALLOCATOR: IrqSpinLock<FrameAllocator> = IrqSpinLock::new(FrameAllocator {
+    bitmap: [0; BITMAP_WORDS],
+    next: 0,
+    statistics: FrameStatistics {
+        total: 0,
+        reserved: 0,
+        allocated: 0,
+        ignored: 0,
+    },
+});
+
+/// Frees the RAM described by the boot memory map, except for what is already in use: low
+/// memory, the kernel image (its page tables and stacks included), the boot information and the
+/// boot modules.
+pub fn init(boot_information: &BootInformation) -> Result<FrameStatistics, FrameAllocatorError> {
+    let mut allocator = FRAME_ALLOCATOR.lock();
+
+    if let Some(memory_map) = boot_information.memory_map() {
+        for area in memory_map.areas() {
+            if area.area_type == MemoryAreaType::Available {
+                allocator.add_available(area.base_address, area.end_address());
+            }
+        }
+    } else if let Some(memory_map) = boot_information.efi_memory_map() {
+        for descriptor in memory_map.descriptors() {
+            let Some(end_address) = descriptor.end_address() else {
+                continue;
+            };
+
+            if descriptor.memory_type.is_free_after_boot_services() {
+                allocator.add_available(descriptor.physical_start, end_address);
+            }
+        }
+    } else {
+        return Err(FrameAllocatorError::NoMemoryMap);
+    }
+
+    allocator.reserve(0, LOW_MEMORY_END);
+
+    let (kernel_start, kernel_end) = layout::kernel_physical_range();
+
+    allocator.reserve(kernel_start as u64, kernel_end as u64);
+
+    allocator.reserve(
+        layout::virtual_to_physical(boot_information.start_address()) as u64,
+        layout::virtual_to_physical(boot_information.end_address()) as u64,
+    );
+
+    for module in boot_information.modules() {
+        allocator.reserve(module.start_address as u64, module.end_address as u64);
+    }
+
+    Ok(allocator.statistics)
+}
+
+pub fn allocate() -> Option<Frame> {
+    FRAME_ALLOCATOR.lock().allocate()
+}
+
+/// Allocates `count` physically contiguous frames, the first one aligned to `alignment` frames
+/// (a power of two), as needed for DMA buffers and large pages.
+pub fn allocate_contiguous(count: usize, alignment: usize) -> Option<Frame> {
+    assert!(alignment.is_power_of_two());
+
+    if count == 0 {
+        return None;
+    }
+
+    FRAME_ALLOCATOR.lock().allocate_contiguous(count, alignment)
+}
+
+pub fn free(frame: Frame) {
+    FRAME_ALLOCATOR.lock().free(frame, 1);
+}
+
+/// Frees frames allocated together by `allocate_contiguous`.
+pub fn free_contiguous(first: Frame, count: usize) {
+    FRAME_ALLOCATOR.lock().free(first, count);
+}
+
+pub fn statistics() -> FrameStatistics {
+    FRAME_ALLOCATOR.lock().statistics
+}
@@ -0,0 +1,180 @@
+//! The kernel heap behind the `alloc` crate. Small allocations come from object caches, one per
+//! power of two size, which keeps them from fragmenting the rest. Larger ones come from a free list
+//! over a virtual region (see layout.rs) that is mapped as it fills up, and never shrinks.
+
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    ptr::{self, NonNull},
+};
+
+use crate::{
+    arch::x86_64::paging::{AddressSpace, MapError, PageFlags},
+    memory::{
+        allocators::{linked_list_allocator::LinkedListAllocator, slab_allocator::ObjectCache},
+        frame_allocator::FRAME_SIZE,
+        layout::{KERNEL_HEAP_MAX_SIZE, KERNEL_HEAP_START},
+    },
+    sync::irq_spin_lock::IrqSpinLock,
+};
+
+/// Mapped by `init`.
+const INITIAL_SIZE: usize = 1 << 20;
+
+/// Smallest step the heap grows by.
+const GROWTH_SIZE: usize = 256 << 10;
+
+/// Allocations up to the largest size are rounded up to the next one. Objects are aligned to their
+/// size, so the alignment of a layout is covered by rounding up to it.
+static SIZE_CLASSES: [ObjectCache; 7] = [
+    ObjectCache::new("heap-16", 16, 16, None),
+    ObjectCache::new("heap-32", 32, 32, None),
+    ObjectCache::new("heap-64", 64, 64, None),
+    ObjectCache::new("heap-128", 128, 128, None),
+    ObjectCache::new("heap-256", 256, 256, None),
+    ObjectCache::new("heap-512", 512, 512, None),
+    ObjectCache::new("heap-1024", 1024, 1024, None),
+];
+
+#[derive(Debug)]
+pub enum HeapError {
+    /// The heap reached `KERNEL_HEAP_MAX_SIZE`.
+    RegionFull,
+    OutOfMemory,
+    Map(MapError),
+}
+
+/// Of the free list. Size classes have their own statistics (see `slab_allocator::caches`).
+#[derive(Debug, Clone, Copy)]
+pub struct HeapStatistics {
+    /// Bytes mapped so far.
+    pub size: usize,
+    /// Bytes in allocated blocks, rounded up to the block size.
+    pub used: usize,
+}
+
+struct Heap {
+    allocator: LinkedListAllocator,
+    /// End of the mapped part of the region, 0 until `init`.
+    end: usize,
+}
+
+impl Heap {
+    /// Maps at least `minimum` more bytes at the end of the heap. What could be mapped is added
+    /// to the free list even if the rest fails.
+    fn grow(&mut self, minimum: usize) -> Result<(), HeapError> {
+        let size = minimum
+            .max(GROWTH_SIZE)
+            .checked_next_multiple_of(FRAME_SIZE as usize)
+            .ok_or(HeapError::RegionFull)?;
+
+        if self
+            .end
+            .checked_add(size)
+            .is_none_or(|end| end > KERNEL_HEAP_START + KERNEL_HEAP_MAX_SIZE)
+        {
+            return Err(HeapError::RegionFull);
+        }
+
+        let end = self.end + size;
+        let result = AddressSpace::kernel().mapper().map_new_frames(
+            self.end as u64,
+            end as u64,
+            PageFlags::WRITABLE | PageFlags::NO_EXECUTE,
+        );
+        let mapped_end = result.map_or_else(|(page, _)| page as usize, |()| end);
+
+        unsafe { self.allocator.add_region(self.end, mapped_end - self.end) };
+
+        self.end = mapped_end;
+
+        result.map_err(|(_, error)| match error {
+            MapError::OutOfMemory => HeapError::OutOfMemory,
+            error => HeapError::Map(error),
+        })
+    }
+}
+
+pub struct KernelHeap(IrqSpinLock<Heap>);
+
+#[global_allocator]
+static HEAP: KernelHeap = KernelHeap(IrqSpinLock::new(Heap {
+    allocator: LinkedListAllocator::new(),
+    end: 0,
+}));
+
+unsafe impl GlobalAlloc for KernelHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if let Some(pointer) = size_class(layout).and_then(ObjectCache::allocate) {
+            return pointer.as_ptr();
+        }
+
+        let mut heap = self.0.lock();
+
+        if heap.end == 0 {
+            return ptr::null_mut();
+        }
+
+        if let Some(pointer) = heap.allocator.allocate(layout) {
+            return pointer.as_ptr();
+        }
+
+        // Enough for the block even if none of the free space at the end can be used, and
+        // however it has to be aligned.
+        let (size, align) = LinkedListAllocator::block_layout(layout);
+
+        let Some(minimum) = size.checked_add(align) else {
+            return ptr::null_mut();
+        };
+
+        let _ = heap.grow(minimum);
+
+        heap.allocator
+            .allocate(layout)
+            .map_or(ptr::null_mut(), NonNull::as_ptr)
+    }
+
+    unsafe fn dealloc(&self, pointer: *mut u8, layout: Layout) {
+        let Some(pointer) = NonNull::new(pointer) else {
+            return;
+        };
+
+        // Small allocations fall back to the free list when no slab could be allocated.
+        if is_in_heap_region(pointer.as_ptr() as usize) {
+            self.0.lock().allocator.deallocate(pointer, layout);
+        } else {
+            size_class(layout)
+                .expect("freeing an object that is not from the heap")
+                .free(pointer);
+        }
+    }
+}
+
+fn size_class(layout: Layout) -> Option<&'static ObjectCache> {
+    let size = layout.size().max(layout.align()).next_power_of_two();
+
+    SIZE_CLASSES
+        .iter()
+        .find(|cache| cache.object_size() >= size)
+}
+
+fn is_in_heap_region(address: usize) -> bool {
+    (KERNEL_HEAP_START..KERNEL_HEAP_START + KERNEL_HEAP_MAX_SIZE).contains(&address)
+}
+
+/// Maps the start of the heap. Runs after `paging::init`, before anything is allocated.
+pub fn init() -> Result<(), HeapError> {
+    let mut heap = HEAP.0.lock();
+
+    heap.end = KERNEL_HEAP_START;
+    heap.grow(INITIAL_SIZE)
+}
+
+pub fn statistics() -> HeapStatistics {
+    let heap = HEAP.0.lock();
+    let size = heap.end.saturating_sub(KERNEL_HEAP_START);
+
+    HeapStatistics {
+        size,
+        used: size - heap.allocator.free(),
+    }
+}
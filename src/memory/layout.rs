@@ -0,0 +1,75 @@
+/// Virtual address the first 1 GiB of physical memory is mapped at by boot.S. The kernel image is
+/// linked inside this window (see linker.ld).
+pub const KERNEL_VIRTUAL_BASE: usize = 0xFFFF_FFFF_8000_0000;
+
+/// Size of the physical memory window mapped at `KERNEL_VIRTUAL_BASE`.
+pub const KERNEL_WINDOW_SIZE: usize = 1 << 30;
+
+/// Virtual address the first 4 GiB of physical memory, device memory included, are mapped at by boot.S.
+/// Everything below the higher half is left for user space.
+pub const PHYSICAL_MEMORY_OFFSET: usize = 0xFFFF_8000_0000_0000;
+
+/// Size of the direct map at `PHYSICAL_MEMORY_OFFSET`.
+pub const PHYSICAL_MEMORY_WINDOW_SIZE: usize = 4 << 30;
+
+/// Where the last GiB of the direct map starts, which boot.S maps uncached for the MMIO hole.
+pub const UNCACHED_PHYSICAL_MEMORY_START: usize = 3 << 30;
+
+/// Virtual region the kernel heap grows into, in the PML4 entry after the direct map.
+pub const KERNEL_HEAP_START: usize = 0xFFFF_8080_0000_0000;
+
+/// Most the kernel heap can grow to.
+pub const KERNEL_HEAP_MAX_SIZE: usize = 4 << 30;
+
+/// Virtual region the thread stacks are mapped in, in the PML4 entry after the heap.
+pub const THREAD_STACKS_START: usize = 0xFFFF_8100_0000_0000;
+
+/// End of the lower canonical half, the only addresses user mode can map.
+pub const USER_SPACE_END: usize = 0x0000_8000_0000_0000;
+
+extern "C" {
+    static kernel_physical_start: u8;
+    static kernel_physical_end: u8;
+}
+
+/// Address of `physical_address` in the direct map. Only valid for the first 4 GiB.
+#[inline(always)]
+pub const fn physical_to_virtual(physical_address: usize) -> usize {
+    physical_address + PHYSICAL_MEMORY_OFFSET
+}
+
+/// Physical address of a kernel image or direct map address.
+#[inline(always)]
+pub const fn virtual_to_physical(virtual_address: usize) -> usize {
+    if virtual_address >= KERNEL_VIRTUAL_BASE {
+        virtual_address - KERNEL_VIRTUAL_BASE
+    } else {
+        virtual_address - PHYSICAL_MEMORY_OFFSET
+    }
+}
+
+/// Memory at `physical_address` through the direct map, `None` if it extends past the window.
+///
+/// # Safety
+///
+/// The memory must stay valid and must not be written to while the slice is in use.
+pub unsafe fn physical_slice(physical_address: u64, length: usize) -> Option<&'static [u8]> {
+    let end = physical_address.checked_add(length as u64)?;
+
+    if end > PHYSICAL_MEMORY_WINDOW_SIZE as u64 {
+        return None;
+    }
+
+    Some(core::slice::from_raw_parts(
+        physical_to_virtual(physical_address as usize) as *const u8,
+        length,
+    ))
+}
+
+/// Physical addresses of the loaded kernel image, including .bss.
+pub fn kernel_physical_range() -> (usize, usize) {
+    (
+        &raw const kernel_physical_start as usize,
+        &raw const kernel_physical_end as usize,
+    )
+}
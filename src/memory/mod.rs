@@ -1 +1,5 @@
+pub mod allocators;
+pub mod frame_allocator;
+pub mod heap;
+pub mod layout;
 pub mod util;
@@ -1,9 +1,11 @@
+use super::repr::ipv4::IpAddress;
+
 pub enum NetInterfaceStatus {
     Up,
     Down,
 }
 
 pub struct NetInterface {
-    state: NetInterfaceStatus,
-    ip: IpAddress,
+    pub state: NetInterfaceStatus,
+    pub ip: IpAddress,
 }
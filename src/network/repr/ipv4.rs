@@ -1 +1,92 @@
+use core::{
+    fmt::{Display, Formatter, Result},
+    str::FromStr,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct IpAddress(pub [u8; 4]);
+
+impl Display for IpAddress {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> Result {
+        formatter.write_fmt(format_args!(
+            "{}.{}.{}.{}",
+            self.0[0], self.0[1], self.0[2], self.0[3]
+        ))
+    }
+}
+
+#[derive(Debug)]
+pub struct AddressParseError;
+
+impl FromStr for IpAddress {
+    type Err = AddressParseError;
+
+    fn from_str(value: &str) -> core::result::Result<Self, Self::Err> {
+        let mut octets = [0; 4];
+        let mut parts = value.split('.');
+
+        for octet in octets.iter_mut() {
+            *octet = parts
+                .next()
+                .and_then(|part| part.parse().ok())
+                .ok_or(AddressParseError)?;
+        }
+
+        if parts.next().is_some() {
+            return Err(AddressParseError);
+        }
+
+        Ok(IpAddress(octets))
+    }
+}
+
+/// An address together with the prefix length of its network, e.g. `192.168.100.2/24`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ipv4Cidr {
+    pub address: IpAddress,
+    pub prefix_length: u8,
+}
+
+impl Ipv4Cidr {
+    pub const fn new(address: IpAddress, prefix_length: u8) -> Self {
+        Self {
+            address,
+            prefix_length,
+        }
+    }
+
+    pub fn netmask(&self) -> IpAddress {
+        let mask = u32::MAX
+            .checked_shl(32 - self.prefix_length as u32)
+            .unwrap_or(0);
+
+        IpAddress(mask.to_be_bytes())
+    }
+}
+
+impl Display for Ipv4Cidr {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> Result {
+        formatter.write_fmt(format_args!("{}/{}", self.address, self.prefix_length))
+    }
+}
+
+impl FromStr for Ipv4Cidr {
+    type Err = AddressParseError;
+
+    /// A missing prefix length means a single host (`/32`).
+    fn from_str(value: &str) -> core::result::Result<Self, Self::Err> {
+        let (address, prefix_length) = match value.split_once('/') {
+            Some((address, prefix_length)) => (
+                address,
+                prefix_length.parse().map_err(|_| AddressParseError)?,
+            ),
+            None => (value, 32),
+        };
+
+        if prefix_length > 32 {
+            return Err(AddressParseError);
+        }
+
+        Ok(Ipv4Cidr::new(address.parse()?, prefix_length))
+    }
+}
@@ -0,0 +1,4 @@
+pub mod ipv4;
+pub mod l2;
+pub mod tcp;
+pub mod udp;
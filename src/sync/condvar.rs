@@ -0,0 +1,63 @@
+use crate::{
+    sync::{mutex::MutexGuard, wait_queue::WaitQueue},
+    thread,
+};
+
+/// Waits for a condition on data behind a `Mutex`.
+pub struct Condvar {
+    waiters: WaitQueue,
+}
+
+impl Condvar {
+    pub const fn new() -> Self {
+        Self {
+            waiters: WaitQueue::new(),
+        }
+    }
+
+    /// Unlocks the mutex and blocks until notified, then locks it again. Wake ups can be
+    /// spurious, see `wait_while`.
+    pub fn wait<'a, T>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        let mutex = guard.mutex();
+
+        // Queued before unlocking, so a notification sent right after is not lost.
+        self.waiters.prepare_to_wait();
+
+        drop(guard);
+
+        if thread::is_started() {
+            thread::park();
+        }
+
+        self.waiters.finish_wait();
+
+        mutex.lock()
+    }
+
+    /// Blocks while `condition` holds for the protected value.
+    pub fn wait_while<'a, T>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        mut condition: impl FnMut(&mut T) -> bool,
+    ) -> MutexGuard<'a, T> {
+        while condition(&mut guard) {
+            guard = self.wait(guard);
+        }
+
+        guard
+    }
+
+    pub fn notify_one(&self) -> bool {
+        self.waiters.notify_one()
+    }
+
+    pub fn notify_all(&self) -> usize {
+        self.waiters.notify_all()
+    }
+}
+
+impl Default for Condvar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
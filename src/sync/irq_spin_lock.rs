@@ -0,0 +1,92 @@
+use core::{
+    mem::ManuallyDrop,
+    ops::{Deref, DerefMut},
+};
+
+use spin::{Mutex, MutexGuard};
+
+use crate::arch::interrupts;
+
+/// A spinlock that keeps interrupts disabled while held, so it can be shared with interrupt
+/// handlers without deadlocking the CPU that holds it.
+pub struct IrqSpinLock<T> {
+    inner: Mutex<T>,
+}
+
+/// Restores the interrupt flag saved by [`IrqSpinLock::lock`] when dropped, so guards can nest.
+pub struct IrqSpinLockGuard<'a, T> {
+    guard: ManuallyDrop<MutexGuard<'a, T>>,
+    interrupts_enabled: bool,
+}
+
+impl<T> IrqSpinLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            inner: Mutex::new(value),
+        }
+    }
+
+    pub fn lock(&self) -> IrqSpinLockGuard<'_, T> {
+        let interrupts_enabled = interrupts::is_enabled();
+
+        interrupts::disable();
+
+        IrqSpinLockGuard {
+            guard: ManuallyDrop::new(self.inner.lock()),
+            interrupts_enabled,
+        }
+    }
+
+    pub fn try_lock(&self) -> Option<IrqSpinLockGuard<'_, T>> {
+        let interrupts_enabled = interrupts::is_enabled();
+
+        interrupts::disable();
+
+        match self.inner.try_lock() {
+            Some(guard) => Some(IrqSpinLockGuard {
+                guard: ManuallyDrop::new(guard),
+                interrupts_enabled,
+            }),
+            None => {
+                if interrupts_enabled {
+                    interrupts::enable();
+                }
+
+                None
+            }
+        }
+    }
+
+    /// # Safety
+    ///
+    /// Only meant for paths that never return, such as the panic handler, where the holder will
+    /// not run again.
+    pub unsafe fn force_unlock(&self) {
+        self.inner.force_unlock();
+    }
+}
+
+impl<T> Deref for IrqSpinLockGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for IrqSpinLockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<T> Drop for IrqSpinLockGuard<'_, T> {
+    fn drop(&mut self) {
+        // Release the lock before interrupts can come in and try to take it.
+        unsafe { ManuallyDrop::drop(&mut self.guard) };
+
+        if self.interrupts_enabled {
+            interrupts::enable();
+        }
+    }
+}
@@ -0,0 +1,6 @@
+pub mod condvar;
+pub mod irq_spin_lock;
+pub mod mutex;
+pub mod rwlock;
+pub mod semaphore;
+pub mod wait_queue;
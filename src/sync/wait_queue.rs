@@ -0,0 +1,147 @@
+use crate::{
+    sync::irq_spin_lock::IrqSpinLock,
+    thread::{self, ThreadId, MAX_THREADS},
+};
+
+/// Threads waiting for something, woken in FIFO order. Waking is safe from interrupt handlers,
+/// waiting is not.
+pub struct WaitQueue {
+    waiters: IrqSpinLock<Waiters>,
+}
+
+struct Waiters {
+    threads: [Option<ThreadId>; MAX_THREADS],
+    head: usize,
+    length: usize,
+}
+
+impl Waiters {
+    /// A thread woken spuriously may still be queued when it waits again, it keeps its place.
+    fn push(&mut self, id: ThreadId) {
+        if self.iter().any(|waiter| waiter == id) || self.length == MAX_THREADS {
+            return;
+        }
+
+        self.threads[(self.head + self.length) % MAX_THREADS] = Some(id);
+        self.length += 1;
+    }
+
+    fn pop(&mut self) -> Option<ThreadId> {
+        if self.length == 0 {
+            return None;
+        }
+
+        let id = self.threads[self.head].take();
+
+        self.head = (self.head + 1) % MAX_THREADS;
+        self.length -= 1;
+
+        id
+    }
+
+    /// Keeps the order of the others.
+    fn remove(&mut self, id: ThreadId) {
+        let Some(position) = self.iter().position(|waiter| waiter == id) else {
+            return;
+        };
+
+        for offset in position..self.length - 1 {
+            self.threads[(self.head + offset) % MAX_THREADS] =
+                self.threads[(self.head + offset + 1) % MAX_THREADS];
+        }
+
+        self.threads[(self.head + self.length - 1) % MAX_THREADS] = None;
+        self.length -= 1;
+    }
+
+    fn iter(&self) -> impl Iterator<Item = ThreadId> + '_ {
+        (0..self.length).filter_map(|offset| self.threads[(self.head + offset) % MAX_THREADS])
+    }
+}
+
+impl WaitQueue {
+    pub const fn new() -> Self {
+        Self {
+            waiters: IrqSpinLock::new(Waiters {
+                threads: [None; MAX_THREADS],
+                head: 0,
+                length: 0,
+            }),
+        }
+    }
+
+    /// Blocks until `condition` holds. It is checked with the queue locked, so a notification
+    /// sent after the state it depends on changed cannot be missed. Before the scheduler starts,
+    /// this spins.
+    pub fn wait_until(&self, mut condition: impl FnMut() -> bool) {
+        loop {
+            {
+                let mut waiters = self.waiters.lock();
+
+                if condition() {
+                    // Still queued if the last park returned without a notification, which would
+                    // otherwise go to this thread instead of one that waits.
+                    if thread::is_started() {
+                        waiters.remove(thread::current());
+                    }
+
+                    return;
+                }
+
+                if thread::is_started() {
+                    waiters.push(thread::current());
+                }
+            }
+
+            if thread::is_started() {
+                thread::park();
+            } else {
+                core::hint::spin_loop();
+            }
+        }
+    }
+
+    /// Queues the calling thread, which must then call `thread::park` and `finish_wait`. A
+    /// notification sent in between makes the park return at once.
+    pub fn prepare_to_wait(&self) {
+        if thread::is_started() {
+            self.waiters.lock().push(thread::current());
+        }
+    }
+
+    /// Takes the calling thread off the queue once its `park` returned, which it may do without a
+    /// notification.
+    pub fn finish_wait(&self) {
+        if thread::is_started() {
+            self.waiters.lock().remove(thread::current());
+        }
+    }
+
+    /// Wakes the longest waiting thread. Returns whether there was one.
+    pub fn notify_one(&self) -> bool {
+        let waiter = self.waiters.lock().pop();
+
+        if let Some(id) = waiter {
+            thread::unpark(id);
+        }
+
+        waiter.is_some()
+    }
+
+    /// Wakes every waiting thread, returning how many there were.
+    pub fn notify_all(&self) -> usize {
+        let mut count = 0;
+
+        while self.notify_one() {
+            count += 1;
+        }
+
+        count
+    }
+}
+
+impl Default for WaitQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
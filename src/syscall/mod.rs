@@ -0,0 +1,150 @@
+//! System calls. User code puts the number in RAX and up to six arguments in RDI, RSI, RDX, R10,
+//! R8 and R9, then executes `syscall`. The result comes back in RAX: a value on success, or a
+//! negated `SyscallError` code.
+
+use core::{ptr, str};
+
+use crate::{
+    arch::{
+        cpu,
+        paging::{self, PageFlags},
+    },
+    memory::{frame_allocator::FRAME_SIZE, layout::USER_SPACE_END},
+    thread, time,
+};
+
+/// `write(buffer, length) -> written`: prints UTF-8 text to the console.
+pub const WRITE: u64 = 0;
+/// `exit(code) -> !`.
+pub const EXIT: u64 = 1;
+/// `yield()`: gives up the CPU.
+pub const YIELD: u64 = 2;
+/// `get_time(clock) -> nanoseconds`, see `CLOCK_MONOTONIC` and `CLOCK_REALTIME`.
+pub const GET_TIME: u64 = 3;
+
+/// Nanoseconds since boot.
+pub const CLOCK_MONOTONIC: u64 = 0;
+/// Nanoseconds since the Unix epoch.
+pub const CLOCK_REALTIME: u64 = 1;
+
+const WRITE_CHUNK_SIZE: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u64)]
+pub enum SyscallError {
+    UnknownSyscall = 1,
+    InvalidAddress = 2,
+    InvalidArgument = 3,
+}
+
+type SyscallHandler = fn(&[u64; 6]) -> Result<u64, SyscallError>;
+
+/// Indexed by syscall number. Each entry unpacks the raw registers into the handler's arguments.
+const SYSCALLS: [SyscallHandler; 4] = [
+    |arguments| write(arguments[0], arguments[1] as usize),
+    |arguments| exit(arguments[0] as i32),
+    |_| yield_now(),
+    |arguments| get_time(arguments[0]),
+];
+
+pub fn dispatch(number: u64, arguments: &[u64; 6]) -> u64 {
+    let result = match SYSCALLS.get(number as usize) {
+        Some(handler) => handler(arguments),
+        None => Err(SyscallError::UnknownSyscall),
+    };
+
+    match result {
+        Ok(value) => value,
+        Err(error) => (error as u64).wrapping_neg(),
+    }
+}
+
+/// Writes as much as the text is valid UTF-8, returning the number of bytes written.
+fn write(address: u64, length: usize) -> Result<u64, SyscallError> {
+    check_user_range(address, length)?;
+
+    let mut buffer = [0; WRITE_CHUNK_SIZE];
+    // Start of a character split between two chunks, moved to the front of the buffer.
+    let mut carried = 0;
+    let mut copied = 0;
+
+    while copied < length {
+        let count = (length - copied).min(WRITE_CHUNK_SIZE - carried);
+        let filled = carried + count;
+
+        copy_from_user(&mut buffer[carried..filled], address + copied as u64);
+        copied += count;
+
+        let valid = match str::from_utf8(&buffer[..filled]) {
+            Ok(text) => text.len(),
+            Err(error) if error.error_len().is_none() && copied < length => error.valid_up_to(),
+            Err(error) => {
+                print!("{}", unsafe {
+                    str::from_utf8_unchecked(&buffer[..error.valid_up_to()])
+                });
+
+                return Ok((copied - filled + error.valid_up_to()) as u64);
+            }
+        };
+
+        print!("{}", unsafe { str::from_utf8_unchecked(&buffer[..valid]) });
+
+        buffer.copy_within(valid..filled, 0);
+        carried = filled - valid;
+    }
+
+    Ok(length as u64)
+}
+
+/// Ends the calling thread, which is all a user program runs in.
+fn exit(code: i32) -> Result<u64, SyscallError> {
+    println!("User program exited with code {}", code);
+
+    thread::exit();
+}
+
+fn yield_now() -> Result<u64, SyscallError> {
+    thread::yield_now();
+
+    Ok(0)
+}
+
+fn get_time(clock: u64) -> Result<u64, SyscallError> {
+    match clock {
+        CLOCK_MONOTONIC => Ok(time::monotonic_now().as_nanoseconds()),
+        CLOCK_REALTIME => Ok(time::wall_clock_now().as_nanos() as u64),
+        _ => Err(SyscallError::InvalidArgument),
+    }
+}
+
+/// Checks that every page of the range is mapped for user mode, so copying it cannot fault.
+fn check_user_range(address: u64, length: usize) -> Result<(), SyscallError> {
+    let end = match address.checked_add(length as u64) {
+        Some(end) if end <= USER_SPACE_END as u64 => end,
+        _ => return Err(SyscallError::InvalidAddress),
+    };
+
+    let mut page = address / FRAME_SIZE * FRAME_SIZE;
+
+    while page < end {
+        match paging::translate(page) {
+            Some(translation) if translation.flags.contains(PageFlags::USER) => {}
+            _ => return Err(SyscallError::InvalidAddress),
+        }
+
+        page += FRAME_SIZE;
+    }
+
+    Ok(())
+}
+
+/// `source` must have been checked with `check_user_range`.
+fn copy_from_user(destination: &mut [u8], source: u64) {
+    cpu::with_user_access(|| unsafe {
+        ptr::copy_nonoverlapping(
+            source as *const u8,
+            destination.as_mut_ptr(),
+            destination.len(),
+        );
+    });
+}
@@ -0,0 +1,78 @@
+use core::{
+    future::Future,
+    pin::pin,
+    sync::atomic::{AtomicU64, Ordering},
+    task::{Context, Poll},
+};
+
+use crate::{
+    arch::interrupts,
+    sync::wait_queue::WaitQueue,
+    task::{waker, MAX_TASKS, TASKS},
+    thread::{self, ThreadError, ThreadId},
+};
+
+// One bit per task slot, set when the task is woken.
+const _: () = assert!(MAX_TASKS <= u64::BITS as usize);
+
+static READY: AtomicU64 = AtomicU64::new(0);
+/// The executor thread, parked while no task is ready.
+static EXECUTOR: WaitQueue = WaitQueue::new();
+
+/// Marks the task in slot `index` ready. Safe from interrupt handlers.
+pub(super) fn schedule(index: usize) {
+    READY.fetch_or(1 << index, Ordering::Release);
+    EXECUTOR.notify_one();
+}
+
+/// Starts the thread that polls the spawned tasks.
+pub fn start() -> Result<ThreadId, ThreadError> {
+    thread::spawn("executor", executor_thread, 0)
+}
+
+/// Polls woken tasks, parking while there are none. There must only be one thread running it.
+pub fn run() -> ! {
+    loop {
+        EXECUTOR.wait_until(|| READY.load(Ordering::Acquire) != 0);
+
+        let mut ready = READY.swap(0, Ordering::AcqRel);
+
+        while ready != 0 {
+            let index = ready.trailing_zeros() as usize;
+            let waker = waker::task_waker(index);
+            let mut context = Context::from_waker(&waker);
+
+            // A task woken while it is polled has its bit set again and runs in the next round.
+            let _ = TASKS[index].poll(&mut context);
+
+            ready &= ready - 1;
+        }
+    }
+}
+
+/// Polls `future` on the calling thread until it completes, parking in between.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let current = thread::is_started().then(thread::current);
+    let waker = waker::thread_waker(current);
+    let mut context = Context::from_waker(&waker);
+
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+            return output;
+        }
+
+        if current.is_some() {
+            thread::park();
+        } else if interrupts::is_enabled() {
+            // Without threads only an interrupt can make progress.
+            interrupts::halt();
+        } else {
+            core::hint::spin_loop();
+        }
+    }
+}
+
+fn executor_thread(_: usize) {
+    run();
+}
@@ -0,0 +1,143 @@
+//! Async tasks, polled by the executor thread. Tasks live in a fixed table and their futures are
+//! stored in place, so spawning does not allocate.
+
+pub mod executor;
+pub mod waker;
+
+use core::{
+    cell::UnsafeCell,
+    future::Future,
+    mem::{align_of, size_of, MaybeUninit},
+    pin::Pin,
+    ptr,
+    sync::atomic::{AtomicU8, Ordering},
+    task::{Context, Poll},
+};
+
+pub const MAX_TASKS: usize = 64;
+
+/// Largest future a task can hold. `async` blocks keep every local that lives across an `await`
+/// in the future, so large buffers belong in statics.
+pub const TASK_STORAGE_SIZE: usize = 1024;
+const TASK_STORAGE_ALIGN: usize = 16;
+
+const FREE: u8 = 0;
+/// Claimed by `spawn`, the future is being moved in.
+const INITIALIZING: u8 = 1;
+const OCCUPIED: u8 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TaskId(usize);
+
+#[derive(Debug)]
+pub enum SpawnError {
+    TooManyTasks,
+    FutureTooLarge { size: usize, align: usize },
+}
+
+#[repr(C, align(16))]
+struct TaskStorage([MaybeUninit<u8>; TASK_STORAGE_SIZE]);
+
+type PollFunction = unsafe fn(*mut u8, &mut Context<'_>) -> Poll<()>;
+type DropFunction = unsafe fn(*mut u8);
+
+/// A slot of the task table: a pinned future and the functions that poll and drop it.
+pub struct Task {
+    state: AtomicU8,
+    storage: UnsafeCell<TaskStorage>,
+    poll: UnsafeCell<Option<PollFunction>>,
+    drop: UnsafeCell<Option<DropFunction>>,
+}
+
+// The future is only touched by `spawn` before the slot is published, and by the executor after.
+unsafe impl Sync for Task {}
+
+impl Task {
+    const fn new() -> Self {
+        Self {
+            state: AtomicU8::new(FREE),
+            storage: UnsafeCell::new(TaskStorage([MaybeUninit::uninit(); TASK_STORAGE_SIZE])),
+            poll: UnsafeCell::new(None),
+            drop: UnsafeCell::new(None),
+        }
+    }
+
+    /// Polls the future, dropping it and freeing the slot once it completes. Only the executor
+    /// calls this.
+    fn poll(&self, context: &mut Context<'_>) -> Poll<()> {
+        if self.state.load(Ordering::Acquire) != OCCUPIED {
+            return Poll::Ready(());
+        }
+
+        unsafe {
+            let storage = self.storage.get() as *mut u8;
+            let poll = (*self.poll.get()).unwrap();
+            let result = poll(storage, context);
+
+            if result.is_ready() {
+                (*self.drop.get()).unwrap()(storage);
+
+                self.state.store(FREE, Ordering::Release);
+            }
+
+            result
+        }
+    }
+}
+
+static TASKS: [Task; MAX_TASKS] = [const { Task::new() }; MAX_TASKS];
+
+/// Queues `future` to be polled by the executor.
+pub fn spawn<F>(future: F) -> Result<TaskId, SpawnError>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    if size_of::<F>() > TASK_STORAGE_SIZE || align_of::<F>() > TASK_STORAGE_ALIGN {
+        return Err(SpawnError::FutureTooLarge {
+            size: size_of::<F>(),
+            align: align_of::<F>(),
+        });
+    }
+
+    let (index, task) = TASKS
+        .iter()
+        .enumerate()
+        .find(|(_, task)| {
+            task.state
+                .compare_exchange(FREE, INITIALIZING, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+        })
+        .ok_or(SpawnError::TooManyTasks)?;
+
+    unsafe {
+        // The slot never moves, so the future stays pinned until it is dropped in place.
+        ptr::write(task.storage.get() as *mut F, future);
+        *task.poll.get() = Some(poll_future::<F>);
+        *task.drop.get() = Some(drop_future::<F>);
+    }
+
+    task.state.store(OCCUPIED, Ordering::Release);
+
+    executor::schedule(index);
+
+    Ok(TaskId(index))
+}
+
+/// Tasks spawned and not yet completed.
+pub fn count() -> usize {
+    TASKS
+        .iter()
+        .filter(|task| task.state.load(Ordering::Relaxed) != FREE)
+        .count()
+}
+
+unsafe fn poll_future<F: Future<Output = ()>>(
+    storage: *mut u8,
+    context: &mut Context<'_>,
+) -> Poll<()> {
+    Pin::new_unchecked(&mut *(storage as *mut F)).poll(context)
+}
+
+unsafe fn drop_future<F>(storage: *mut u8) {
+    ptr::drop_in_place(storage as *mut F);
+}
@@ -0,0 +1,98 @@
+use core::task::{RawWaker, RawWakerVTable, Waker};
+
+use crate::{
+    sync::irq_spin_lock::IrqSpinLock,
+    task::executor,
+    thread::{self, ThreadId},
+};
+
+// Both kinds of waker carry a plain integer instead of a pointer, so cloning and dropping them
+// is free and they can be used from interrupt handlers.
+
+static TASK_WAKER_VTABLE: RawWakerVTable =
+    RawWakerVTable::new(clone_task_waker, wake_task, wake_task, drop_waker);
+
+static THREAD_WAKER_VTABLE: RawWakerVTable =
+    RawWakerVTable::new(clone_thread_waker, wake_thread, wake_thread, drop_waker);
+
+/// Queues the task in slot `index` to be polled again.
+pub(super) fn task_waker(index: usize) -> Waker {
+    unsafe { Waker::from_raw(RawWaker::new(index as *const (), &TASK_WAKER_VTABLE)) }
+}
+
+/// Unparks the thread, used by `block_on`. A zero ID, before the scheduler starts, wakes nothing.
+pub(super) fn thread_waker(id: Option<ThreadId>) -> Waker {
+    let id = id.map_or(0, |id| id.as_u64());
+
+    unsafe { Waker::from_raw(RawWaker::new(id as *const (), &THREAD_WAKER_VTABLE)) }
+}
+
+unsafe fn clone_task_waker(data: *const ()) -> RawWaker {
+    RawWaker::new(data, &TASK_WAKER_VTABLE)
+}
+
+unsafe fn wake_task(data: *const ()) {
+    executor::schedule(data as usize);
+}
+
+unsafe fn clone_thread_waker(data: *const ()) -> RawWaker {
+    RawWaker::new(data, &THREAD_WAKER_VTABLE)
+}
+
+unsafe fn wake_thread(data: *const ()) {
+    if !data.is_null() {
+        thread::unpark(ThreadId::from_u64(data as u64));
+    }
+}
+
+unsafe fn drop_waker(_data: *const ()) {}
+
+/// Holds the waker of the future waiting for an event, so an interrupt handler can wake it.
+///
+/// ```ignore
+/// static RECEIVED: AtomicWaker = AtomicWaker::new();
+///
+/// // In the future's `poll`, before checking the device:
+/// RECEIVED.register(context.waker());
+///
+/// // In the interrupt handler:
+/// RECEIVED.wake();
+/// ```
+pub struct AtomicWaker {
+    waker: IrqSpinLock<Option<Waker>>,
+}
+
+impl AtomicWaker {
+    pub const fn new() -> Self {
+        Self {
+            waker: IrqSpinLock::new(None),
+        }
+    }
+
+    /// Replaces the stored waker.
+    pub fn register(&self, waker: &Waker) {
+        let mut stored = self.waker.lock();
+
+        if !stored
+            .as_ref()
+            .is_some_and(|stored| stored.will_wake(waker))
+        {
+            *stored = Some(waker.clone());
+        }
+    }
+
+    /// Wakes and forgets the stored waker, if any.
+    pub fn wake(&self) {
+        let waker = self.waker.lock().take();
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+impl Default for AtomicWaker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,455 @@
+//! Kernel threads and a round-robin scheduler, preempted by the PIT tick.
+//!
+//! The thread table is shared, while each CPU keeps the thread it runs, its idle thread and the
+//! time slice in per-CPU data. Threads only run on the boot CPU for now, the one the tick is routed
+//! to. Each slot has its own stack, mapped the first time the slot is used with an unmapped guard
+//! page below it, and a thread's slot is only given back once it is joined.
+
+use core::{
+    ops::Range,
+    ptr,
+    sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use crate::{
+    arch::x86_64::{
+        context,
+        gdt::{self, STACK_SIZE},
+        idt::InterruptContext,
+        interrupts,
+        irq::{self, IrqResult},
+        paging::{AddressSpace, PageFlags},
+        pit,
+    },
+    memory::{frame_allocator::FRAME_SIZE, layout::THREAD_STACKS_START},
+    percpu,
+    sync::irq_spin_lock::IrqSpinLock,
+    time::{self, Instant},
+};
+
+pub const MAX_THREADS: usize = 64;
+
+/// Ticks a thread runs before the next ready thread gets the CPU.
+const TIME_SLICE_TICKS: u32 = 10;
+
+const BOOT_THREAD_INDEX: usize = 0;
+
+/// Room for a stack and the guard page below it.
+const STACK_SLOT_SIZE: usize = FRAME_SIZE as usize + STACK_SIZE;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ThreadId(u64);
+
+impl ThreadId {
+    pub fn as_u64(&self) -> u64 {
+        self.0
+    }
+
+    /// For code that has to carry the ID in a plain integer, such as a waker.
+    pub(crate) fn from_u64(id: u64) -> Self {
+        Self(id)
+    }
+}
+
+#[derive(Debug)]
+pub enum ThreadError {
+    TooManyThreads,
+    /// No memory was left for the stack.
+    OutOfMemory,
+    /// The thread does not exist, or was already joined.
+    NotFound,
+    JoinSelf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Ready,
+    Running,
+    Sleeping(Instant),
+    Parked,
+    Finished,
+}
+
+struct Thread {
+    id: ThreadId,
+    name: &'static str,
+    state: State,
+    stack_pointer: u64,
+    /// Stack used when entering the kernel from user mode while this thread runs.
+    kernel_stack_top: u64,
+    entry: fn(usize),
+    argument: usize,
+    /// Set by `unpark` when the thread was not parked, so the next `park` returns at once.
+    unpark_token: bool,
+    joiner: Option<ThreadId>,
+}
+
+struct Scheduler {
+    threads: [Option<Thread>; MAX_THREADS],
+}
+
+percpu! {
+    /// Slot of the thread the CPU runs.
+    static CURRENT: AtomicUsize = AtomicUsize::new(BOOT_THREAD_INDEX);
+    /// Slot of the thread the CPU runs when no other one is ready.
+    static IDLE: AtomicUsize = AtomicUsize::new(BOOT_THREAD_INDEX);
+    /// Ticks before the running thread gives way.
+    static SLICE_TICKS_LEFT: AtomicU32 = AtomicU32::new(TIME_SLICE_TICKS);
+}
+
+impl Scheduler {
+    fn current_index(&self) -> usize {
+        CURRENT.get().load(Ordering::Relaxed)
+    }
+
+    fn current(&mut self) -> &mut Thread {
+        let index = self.current_index();
+
+        self.threads[index].as_mut().unwrap()
+    }
+
+    fn index_of(&self, id: ThreadId) -> Option<usize> {
+        self.threads
+            .iter()
+            .position(|thread| thread.as_ref().is_some_and(|thread| thread.id == id))
+    }
+
+    /// The next ready thread after the current one, the current one last. `None` keeps the
+    /// current thread running.
+    fn pick_next(&self) -> Option<usize> {
+        let current = self.current_index();
+        let idle = IDLE.get().load(Ordering::Relaxed);
+
+        let next = (1..=MAX_THREADS)
+            .map(|offset| (current + offset) % MAX_THREADS)
+            .filter(|&index| index != idle)
+            .find(|&index| {
+                self.threads[index].as_ref().is_some_and(|thread| {
+                    thread.state == State::Ready
+                        || (index == current && thread.state == State::Running)
+                })
+            })
+            .unwrap_or(idle);
+
+        (next != current).then_some(next)
+    }
+
+    fn unpark(&mut self, id: ThreadId) {
+        let Some(index) = self.index_of(id) else {
+            return;
+        };
+
+        let thread = self.threads[index].as_mut().unwrap();
+
+        match thread.state {
+            State::Parked => thread.state = State::Ready,
+            State::Finished => {}
+            _ => thread.unpark_token = true,
+        }
+    }
+
+    fn wake_sleepers(&mut self, now: Instant) {
+        for thread in self.threads.iter_mut().flatten() {
+            if let State::Sleeping(deadline) = thread.state {
+                if deadline <= now {
+                    thread.state = State::Ready;
+                }
+            }
+        }
+    }
+}
+
+static SCHEDULER: IrqSpinLock<Scheduler> = IrqSpinLock::new(Scheduler {
+    threads: [const { None }; MAX_THREADS],
+});
+
+static STARTED: AtomicBool = AtomicBool::new(false);
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Turns the running code into the boot thread, starts the idle thread and hooks the scheduler
+/// to the PIT tick. Must run on the boot CPU, after `time::init` and `paging::init`.
+pub fn init() -> Result<(), ThreadError> {
+    {
+        let mut scheduler = SCHEDULER.lock();
+
+        scheduler.threads[BOOT_THREAD_INDEX] = Some(Thread {
+            id: ThreadId(NEXT_ID.fetch_add(1, Ordering::Relaxed)),
+            name: "boot",
+            state: State::Running,
+            stack_pointer: 0,
+            kernel_stack_top: gdt::kernel_stack(),
+            entry: |_| {},
+            argument: 0,
+            unpark_token: false,
+            joiner: None,
+        });
+    }
+
+    let idle = spawn("idle", idle, 0)?;
+
+    // Only picked when nothing else can run.
+    IDLE.get()
+        .store(SCHEDULER.lock().index_of(idle).unwrap(), Ordering::Relaxed);
+
+    STARTED.store(true, Ordering::Release);
+
+    if let Err(error) = irq::register(pit::IRQ_LINE, tick) {
+        println!("warning: threads will not be preempted: {:?}", error);
+    }
+
+    Ok(())
+}
+
+/// Maps the stack of the thread slot, unless an earlier thread in the slot already did, and
+/// returns its top. The page below the stack stays unmapped, so an overflow faults instead of
+/// running into the stack of another slot. The boot thread keeps the stack it was started on.
+fn map_stack(index: usize) -> Result<u64, ThreadError> {
+    let stack = slot_stack(index);
+
+    AddressSpace::kernel()
+        .mapper()
+        .map_new_frames(
+            stack.start,
+            stack.end,
+            PageFlags::WRITABLE | PageFlags::NO_EXECUTE,
+        )
+        .map_err(|_| ThreadError::OutOfMemory)?;
+
+    Ok(stack.end)
+}
+
+/// Stack of the slot, without its guard page.
+fn slot_stack(index: usize) -> Range<u64> {
+    let bottom = (THREAD_STACKS_START + index * STACK_SLOT_SIZE) as u64 + FRAME_SIZE;
+
+    bottom..bottom + STACK_SIZE as u64
+}
+
+/// Stack of the thread slot whose stack or guard page `address` is in. `None` for addresses
+/// outside the thread stacks, such as those in the kernel image.
+pub fn stack_bounds(address: u64) -> Option<Range<u64>> {
+    let index = address.checked_sub(THREAD_STACKS_START as u64)? as usize / STACK_SLOT_SIZE;
+
+    (index < MAX_THREADS).then(|| slot_stack(index))
+}
+
+/// Starts a thread that runs `entry(argument)`.
+pub fn spawn(
+    name: &'static str,
+    entry: fn(usize),
+    argument: usize,
+) -> Result<ThreadId, ThreadError> {
+    let id = ThreadId(NEXT_ID.fetch_add(1, Ordering::Relaxed));
+    let mut scheduler = SCHEDULER.lock();
+
+    let index = scheduler
+        .threads
+        .iter()
+        .position(|thread| thread.is_none())
+        .ok_or(ThreadError::TooManyThreads)?;
+
+    let stack_top = map_stack(index)?;
+    let stack_pointer = unsafe { context::prepare_stack(stack_top, thread_start, index as u64) };
+
+    scheduler.threads[index] = Some(Thread {
+        id,
+        name,
+        state: State::Ready,
+        stack_pointer,
+        kernel_stack_top: stack_top,
+        entry,
+        argument,
+        unpark_token: false,
+        joiner: None,
+    });
+
+    Ok(id)
+}
+
+/// Whether `init` has run, so threads can block.
+pub fn is_started() -> bool {
+    STARTED.load(Ordering::Acquire)
+}
+
+/// Only valid once the scheduler is started.
+pub fn current() -> ThreadId {
+    SCHEDULER.lock().current().id
+}
+
+/// Name given to `spawn`.
+pub fn current_name() -> &'static str {
+    SCHEDULER.lock().current().name
+}
+
+/// Lets the other ready threads run first.
+pub fn yield_now() {
+    reschedule(|_| {});
+}
+
+/// Blocks the calling thread for at least `duration`. Before the scheduler starts, this waits
+/// with `time::sleep`.
+pub fn sleep(duration: Duration) {
+    if !STARTED.load(Ordering::Acquire) {
+        return time::sleep(duration);
+    }
+
+    let deadline = time::monotonic_now() + duration;
+
+    reschedule(|scheduler| scheduler.current().state = State::Sleeping(deadline));
+}
+
+/// Blocks the calling thread until `unpark` is called on it. Returns at once if `unpark` was
+/// called since the last `park`, and may also return spuriously, so callers check their
+/// condition in a loop.
+pub fn park() {
+    reschedule(|scheduler| {
+        let thread = scheduler.current();
+
+        if thread.unpark_token {
+            thread.unpark_token = false;
+        } else {
+            thread.state = State::Parked;
+        }
+    });
+}
+
+pub fn unpark(id: ThreadId) {
+    SCHEDULER.lock().unpark(id);
+}
+
+/// Waits for the thread to finish and frees its slot.
+pub fn join(id: ThreadId) -> Result<(), ThreadError> {
+    let current = current();
+
+    if id == current {
+        return Err(ThreadError::JoinSelf);
+    }
+
+    loop {
+        {
+            let mut scheduler = SCHEDULER.lock();
+            let index = scheduler.index_of(id).ok_or(ThreadError::NotFound)?;
+            let thread = scheduler.threads[index].as_mut().unwrap();
+
+            if thread.state == State::Finished {
+                scheduler.threads[index] = None;
+
+                return Ok(());
+            }
+
+            thread.joiner = Some(current);
+        }
+
+        park();
+    }
+}
+
+/// Ends the calling thread. Its slot stays taken until it is joined. Threads also end by
+/// returning from their entry point.
+pub fn exit() -> ! {
+    reschedule(|scheduler| {
+        let thread = scheduler.current();
+
+        thread.state = State::Finished;
+
+        if let Some(joiner) = thread.joiner {
+            scheduler.unpark(joiner);
+        }
+    });
+
+    // Only reached before the scheduler starts, with nothing to switch to.
+    loop {
+        interrupts::halt();
+    }
+}
+
+/// Applies `update` to the scheduler and switches to the next one, all without letting
+/// interrupts in, so a wake up cannot slip in between.
+fn reschedule(update: impl FnOnce(&mut Scheduler)) {
+    if !STARTED.load(Ordering::Acquire) {
+        return;
+    }
+
+    interrupts::without_interrupts(|| {
+        let (previous_stack_pointer, next_stack_pointer, kernel_stack_top) = {
+            let mut scheduler = SCHEDULER.lock();
+
+            update(&mut scheduler);
+
+            let Some(next) = scheduler.pick_next() else {
+                return;
+            };
+
+            let previous = scheduler.current();
+
+            if previous.state == State::Running {
+                previous.state = State::Ready;
+            }
+
+            let previous_stack_pointer = ptr::addr_of_mut!(previous.stack_pointer);
+
+            CURRENT.get().store(next, Ordering::Relaxed);
+            SLICE_TICKS_LEFT
+                .get()
+                .store(TIME_SLICE_TICKS, Ordering::Relaxed);
+
+            let next = scheduler.current();
+
+            next.state = State::Running;
+
+            (
+                previous_stack_pointer,
+                next.stack_pointer,
+                next.kernel_stack_top,
+            )
+        };
+
+        gdt::set_kernel_stack(kernel_stack_top);
+
+        // The slot of the previous thread is not freed before it is Finished and switched away
+        // from, so the pointer stays valid.
+        unsafe { context::switch(previous_stack_pointer, next_stack_pointer) };
+    });
+}
+
+extern "C" fn thread_start(index: u64) -> ! {
+    let (entry, argument) = {
+        let mut scheduler = SCHEDULER.lock();
+        let thread = scheduler.threads[index as usize].as_mut().unwrap();
+
+        (thread.entry, thread.argument)
+    };
+
+    // Switches happen with interrupts disabled.
+    interrupts::enable();
+
+    entry(argument);
+
+    exit();
+}
+
+fn idle(_: usize) {
+    loop {
+        interrupts::halt();
+    }
+}
+
+fn tick(_context: &mut InterruptContext) -> IrqResult {
+    SCHEDULER.lock().wake_sleepers(time::monotonic_now());
+
+    let slice_ticks_left = SLICE_TICKS_LEFT.get();
+    let ticks_left = slice_ticks_left.load(Ordering::Relaxed).saturating_sub(1);
+
+    slice_ticks_left.store(ticks_left, Ordering::Relaxed);
+
+    // The idle thread gives way as soon as anything is ready.
+    let preempt = ticks_left == 0
+        || CURRENT.get().load(Ordering::Relaxed) == IDLE.get().load(Ordering::Relaxed);
+
+    if preempt {
+        yield_now();
+    }
+
+    IrqResult::Handled
+}
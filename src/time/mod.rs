@@ -0,0 +1,244 @@
+//! Monotonic clock, wall clock and timers, driven by the PIT tick.
+
+pub mod date;
+pub mod timer;
+
+use core::{
+    ops::{Add, Sub},
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use spin::Once;
+
+use crate::{
+    acpi::{self, AddressSpace},
+    arch::x86_64::{
+        hpet::Hpet,
+        idt::InterruptContext,
+        interrupts,
+        irq::{self, IrqResult},
+        pit, rtc, tsc,
+    },
+};
+
+use self::date::DateTime;
+
+/// Frequency of the PIT interrupt that expires timers.
+pub const TICK_FREQUENCY: u64 = 1000;
+
+const PIT_DIVISOR: u16 = (pit::FREQUENCY / TICK_FREQUENCY) as u16;
+
+const NANOSECONDS_PER_SECOND: u64 = 1_000_000_000;
+const CALIBRATION_NANOSECONDS: u64 = 10_000_000;
+
+/// Counter behind `monotonic_now`, from the most to the least precise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockSource {
+    /// Only used when invariant, otherwise its rate changes with the CPU frequency.
+    Tsc,
+    Hpet,
+    /// Counts PIT ticks, so it has a resolution of 1 / `TICK_FREQUENCY`.
+    Pit,
+}
+
+/// A point in time, in nanoseconds since the clock was initialized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Instant(u64);
+
+impl Instant {
+    pub const fn from_nanoseconds(nanoseconds: u64) -> Self {
+        Self(nanoseconds)
+    }
+
+    pub const fn as_nanoseconds(&self) -> u64 {
+        self.0
+    }
+
+    /// Time elapsed from `earlier` to `self`, zero if `earlier` is later.
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        Duration::from_nanos(self.0.saturating_sub(earlier.0))
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        monotonic_now().duration_since(*self)
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, duration: Duration) -> Instant {
+        Instant(
+            self.0
+                .saturating_add(u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX)),
+        )
+    }
+}
+
+impl Sub<Instant> for Instant {
+    type Output = Duration;
+
+    fn sub(self, earlier: Instant) -> Duration {
+        self.duration_since(earlier)
+    }
+}
+
+struct Clock {
+    source: ClockSource,
+    hpet: Option<Hpet>,
+    tsc_frequency: u64,
+    /// Value of the source counter when the clock was initialized.
+    start: u64,
+}
+
+impl Clock {
+    fn counter(&self) -> u64 {
+        match self.source {
+            ClockSource::Tsc => tsc::read(),
+            ClockSource::Hpet => self.hpet.map_or(0, |hpet| hpet.counter()),
+            ClockSource::Pit => TICKS.load(Ordering::Relaxed),
+        }
+    }
+
+    fn frequency(&self) -> u64 {
+        match self.source {
+            ClockSource::Tsc => self.tsc_frequency,
+            ClockSource::Hpet => self.hpet.map_or(1, |hpet| hpet.frequency),
+            ClockSource::Pit => TICK_FREQUENCY,
+        }
+    }
+
+    fn now(&self) -> Instant {
+        let elapsed = self.counter().wrapping_sub(self.start) as u128;
+
+        Instant((elapsed * NANOSECONDS_PER_SECOND as u128 / self.frequency() as u128) as u64)
+    }
+}
+
+static CLOCK: Once<Clock> = Once::new();
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+/// UNIX time, in nanoseconds, at `Instant(0)`.
+static WALL_CLOCK_OFFSET: AtomicU64 = AtomicU64::new(0);
+
+/// Calibrates the TSC, picks a clock source and starts the PIT tick. The HPET is used when ACPI
+/// describes one.
+pub fn init(hpet_table: Option<&acpi::hpet::Hpet>) -> ClockSource {
+    let hpet = hpet_table
+        .filter(|table| table.base_address.address_space == AddressSpace::SystemMemory)
+        .and_then(|table| unsafe { Hpet::init(table.base_address.address) });
+
+    let tsc_frequency = tsc::calibrate(CALIBRATION_NANOSECONDS, || match hpet {
+        Some(hpet) => {
+            let start = hpet.counter();
+            let cycles = hpet.frequency * CALIBRATION_NANOSECONDS / NANOSECONDS_PER_SECOND;
+
+            while hpet.counter() - start < cycles {
+                core::hint::spin_loop();
+            }
+        }
+        None => pit::wait_cycles(
+            (pit::FREQUENCY * CALIBRATION_NANOSECONDS / NANOSECONDS_PER_SECOND) as u16,
+        ),
+    });
+
+    let source = if tsc::is_invariant() {
+        ClockSource::Tsc
+    } else if hpet.is_some() {
+        ClockSource::Hpet
+    } else {
+        ClockSource::Pit
+    };
+
+    let mut clock = Clock {
+        source,
+        hpet,
+        tsc_frequency,
+        start: 0,
+    };
+
+    clock.start = clock.counter();
+
+    CLOCK.call_once(|| clock);
+
+    pit::set_periodic(PIT_DIVISOR);
+
+    if let Err(error) = irq::register(pit::IRQ_LINE, tick) {
+        println!(
+            "warning: failed to register the timer interrupt: {:?}",
+            error
+        );
+    }
+
+    source
+}
+
+/// Time since the clock was initialized, zero before that.
+pub fn monotonic_now() -> Instant {
+    CLOCK.r#try().map_or(Instant(0), |clock| clock.now())
+}
+
+/// Reads the RTC once, later wall clock readings advance with the monotonic clock.
+/// `century_register` is the CMOS index from the FADT, 0 if unknown.
+pub fn init_wall_clock(century_register: u8) -> DateTime {
+    let date_time = rtc::read(century_register);
+    let now = monotonic_now();
+
+    // Dates before 1970, or too far ahead to count in nanoseconds, leave the wall clock at the
+    // epoch.
+    let offset = date_time
+        .to_unix_timestamp()
+        .and_then(|seconds| seconds.checked_mul(NANOSECONDS_PER_SECOND))
+        .map_or(0, |nanoseconds| {
+            nanoseconds.saturating_sub(now.as_nanoseconds())
+        });
+
+    WALL_CLOCK_OFFSET.store(offset, Ordering::Relaxed);
+
+    date_time
+}
+
+/// Time since the UNIX epoch. Only accurate to the second the RTC was read in.
+pub fn wall_clock_now() -> Duration {
+    Duration::from_nanos(
+        WALL_CLOCK_OFFSET
+            .load(Ordering::Relaxed)
+            .saturating_add(monotonic_now().as_nanoseconds()),
+    )
+}
+
+pub fn wall_clock_date_time() -> DateTime {
+    DateTime::from_unix_timestamp(wall_clock_now().as_secs())
+}
+
+/// TSC frequency in Hz, zero before the clock is initialized.
+pub fn tsc_frequency() -> u64 {
+    CLOCK.r#try().map_or(0, |clock| clock.tsc_frequency)
+}
+
+/// PIT interrupts since the clock was initialized.
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+/// Waits for at least `duration`, halting between ticks when interrupts are enabled.
+pub fn sleep(duration: Duration) {
+    let deadline = monotonic_now() + duration;
+
+    while monotonic_now() < deadline {
+        if interrupts::is_enabled() {
+            interrupts::halt();
+        } else {
+            core::hint::spin_loop();
+        }
+    }
+}
+
+fn tick(_context: &mut InterruptContext) -> IrqResult {
+    TICKS.fetch_add(1, Ordering::Relaxed);
+
+    timer::run_expired(monotonic_now());
+
+    IrqResult::Handled
+}
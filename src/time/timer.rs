@@ -0,0 +1,119 @@
+use core::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use crate::sync::irq_spin_lock::IrqSpinLock;
+
+use super::{monotonic_now, Instant};
+
+const MAX_TIMERS: usize = 64;
+
+/// Called from the timer interrupt, so it must not block.
+pub type TimerCallback = fn(TimerId);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimerId(u64);
+
+#[derive(Debug)]
+pub enum TimerError {
+    TooManyTimers,
+}
+
+#[derive(Clone, Copy)]
+struct Timer {
+    id: TimerId,
+    deadline: Instant,
+    period: Option<Duration>,
+    callback: TimerCallback,
+}
+
+static TIMERS: IrqSpinLock<[Option<Timer>; MAX_TIMERS]> = IrqSpinLock::new([None; MAX_TIMERS]);
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Calls `callback` once, `delay` from now.
+pub fn one_shot(delay: Duration, callback: TimerCallback) -> Result<TimerId, TimerError> {
+    add(monotonic_now() + delay, None, callback)
+}
+
+/// Calls `callback` every `period`, until cancelled.
+pub fn periodic(period: Duration, callback: TimerCallback) -> Result<TimerId, TimerError> {
+    add(monotonic_now() + period, Some(period), callback)
+}
+
+/// Returns whether the timer was still pending.
+pub fn cancel(id: TimerId) -> bool {
+    let mut timers = TIMERS.lock();
+
+    match timers
+        .iter_mut()
+        .find(|slot| slot.is_some_and(|timer| timer.id == id))
+    {
+        Some(slot) => {
+            *slot = None;
+            true
+        }
+        None => false,
+    }
+}
+
+fn add(
+    deadline: Instant,
+    period: Option<Duration>,
+    callback: TimerCallback,
+) -> Result<TimerId, TimerError> {
+    let id = TimerId(NEXT_ID.fetch_add(1, Ordering::Relaxed));
+
+    let mut timers = TIMERS.lock();
+    let slot = timers
+        .iter_mut()
+        .find(|slot| slot.is_none())
+        .ok_or(TimerError::TooManyTimers)?;
+
+    *slot = Some(Timer {
+        id,
+        deadline,
+        period,
+        callback,
+    });
+
+    Ok(id)
+}
+
+/// Runs the callbacks of the timers due at `now`, outside of the lock so they can add or cancel
+/// timers.
+pub(super) fn run_expired(now: Instant) {
+    let mut expired: [Option<(TimerId, TimerCallback)>; MAX_TIMERS] = [None; MAX_TIMERS];
+
+    {
+        let mut timers = TIMERS.lock();
+
+        for (slot, expired) in timers.iter_mut().zip(expired.iter_mut()) {
+            let Some(timer) = slot else {
+                continue;
+            };
+
+            if timer.deadline > now {
+                continue;
+            }
+
+            *expired = Some((timer.id, timer.callback));
+
+            match timer.period {
+                // Skip the periods that were missed instead of firing them all at once.
+                Some(period) => {
+                    timer.deadline = timer.deadline + period;
+
+                    if timer.deadline <= now {
+                        timer.deadline = now + period;
+                    }
+                }
+                None => *slot = None,
+            }
+        }
+    }
+
+    for (id, callback) in expired.into_iter().flatten() {
+        callback(id);
+    }
+}